@@ -0,0 +1,10 @@
+; Counter (starts at 0), assembles to the path in counter.txt
+
+        push 0
+loop:   dup
+        load PrintLn
+        swap 0 1
+        call
+        push 1
+        add
+        jump loop
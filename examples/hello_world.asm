@@ -0,0 +1,5 @@
+; Prints "Hello, World!", assembles to the path in hello_world.txt
+
+        load PrintLn
+        push "Hello, World!"
+        call
@@ -0,0 +1,713 @@
+//! Abstract interpretation of decoded code, following the possible stack depths and
+//! value kinds along every path to find errors that are bound to happen once reached.
+
+use std::collections::{HashMap, VecDeque};
+
+use num_bigint::BigUint;
+use thiserror::Error;
+
+use crate::{
+    arithmetic::{self, BinaryOperator},
+    function::{BuiltInFunction, Function},
+    memory::Memory,
+    op::{Code, Op},
+    value::{Key, Value},
+};
+
+/// How many times an op is revisited before its stack depth stops being bounded.
+const WIDEN_AFTER: usize = 4;
+
+/// A problem every run reaching the instruction runs into, indices are counted like
+/// jumps at the top level do.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Problem {
+    #[error(
+        "Instruction {index}: {opcode} needs {needed} value(s) but the stack holds at most {depth}"
+    )]
+    StackUnderflow {
+        index: usize,
+        opcode: &'static str,
+        needed: usize,
+        depth: usize,
+    },
+    #[error("Instruction {index}: {opcode} can't take {operands}")]
+    TypeMismatch {
+        index: usize,
+        opcode: &'static str,
+        operands: String,
+    },
+    #[error("Instruction {index}: call function finds no function on the stack")]
+    NotCallable { index: usize },
+}
+
+impl Problem {
+    pub fn index(&self) -> usize {
+        match self {
+            Problem::StackUnderflow { index, .. }
+            | Problem::TypeMismatch { index, .. }
+            | Problem::NotCallable { index } => *index,
+        }
+    }
+}
+
+/// The set of kinds a stack slot or variable may hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Kinds(u16);
+
+impl Kinds {
+    const BOOLEAN: Kinds = Kinds(1);
+    const INTEGER: Kinds = Kinds(1 << 1);
+    const FLOAT: Kinds = Kinds(1 << 2);
+    const STRING: Kinds = Kinds(1 << 3);
+    const ARRAY: Kinds = Kinds(1 << 4);
+    const MAP: Kinds = Kinds(1 << 5);
+    const NULL: Kinds = Kinds(1 << 6);
+    /// Built-in functions that give nothing back.
+    const SILENT: Kinds = Kinds(1 << 7);
+    /// Built-in functions that give back a value.
+    const RETURNING: Kinds = Kinds(1 << 8);
+    /// User-defined functions, which may give back a value or not and may change globals.
+    const USER: Kinds = Kinds(1 << 9);
+    /// Values marked by make argument.
+    const ARGUMENT: Kinds = Kinds(1 << 10);
+    /// Values marked by make optional argument.
+    const OPTIONAL: Kinds = Kinds(1 << 11);
+
+    const FUNCTION: Kinds = Kinds(Self::SILENT.0 | Self::RETURNING.0 | Self::USER.0);
+    const VALUE: Kinds = Kinds((1 << 10) - 1);
+    const ANY: Kinds = Kinds(Self::VALUE.0 | Self::ARGUMENT.0 | Self::OPTIONAL.0);
+
+    /// Every kind on its own with a value of it, functions share one.
+    const SAMPLES: [Kinds; 8] = [
+        Kinds::BOOLEAN,
+        Kinds::INTEGER,
+        Kinds::FLOAT,
+        Kinds::STRING,
+        Kinds::ARRAY,
+        Kinds::MAP,
+        Kinds::NULL,
+        Kinds::FUNCTION,
+    ];
+
+    fn of(value: &Value) -> Kinds {
+        match value {
+            Value::Boolean(_) => Kinds::BOOLEAN,
+            Value::Integer(_) => Kinds::INTEGER,
+            Value::Float(_) => Kinds::FLOAT,
+            Value::String(_) => Kinds::STRING,
+            Value::Array(_) => Kinds::ARRAY,
+            Value::Map(_) => Kinds::MAP,
+            Value::Null => Kinds::NULL,
+            Value::Function(function) => match function.built_in() {
+                BuiltInFunction::None => Kinds::USER,
+                BuiltInFunction::Print | BuiltInFunction::PrintLn => Kinds::SILENT,
+                _ => Kinds::RETURNING,
+            },
+        }
+    }
+
+    fn sample(self) -> Value {
+        match self {
+            Kinds::BOOLEAN => Value::Boolean(true),
+            Kinds::INTEGER => Value::Integer(1.into()),
+            Kinds::FLOAT => Value::Float(1.0),
+            Kinds::STRING => Value::String("a".to_owned()),
+            Kinds::ARRAY => Value::Array(vec![Value::Integer(1.into())]),
+            Kinds::NULL => Value::Null,
+            Kinds::MAP => Value::Map([(Key::Integer(1.into()), Value::Integer(1.into()))].into()),
+            _ => Value::Function(Function::default()),
+        }
+    }
+
+    /// One value for each kind in the set.
+    fn samples(self) -> Vec<Value> {
+        Kinds::SAMPLES
+            .into_iter()
+            .filter(|kinds| self.intersects(*kinds))
+            .map(Kinds::sample)
+            .collect()
+    }
+
+    fn union(self, other: Kinds) -> Kinds {
+        Kinds(self.0 | other.0)
+    }
+
+    fn intersects(self, other: Kinds) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    fn within(self, other: Kinds) -> bool {
+        self.0 & !other.0 == 0
+    }
+
+    /// The kinds once a marked value is taken out of its marker, which hides what it was.
+    fn values(self) -> Kinds {
+        if self.intersects(Kinds::ARGUMENT.union(Kinds::OPTIONAL)) {
+            Kinds::VALUE
+        } else {
+            self
+        }
+    }
+
+    fn describe(self) -> String {
+        let names = [
+            (Kinds::BOOLEAN, "boolean"),
+            (Kinds::INTEGER, "integer"),
+            (Kinds::FLOAT, "float"),
+            (Kinds::STRING, "string"),
+            (Kinds::ARRAY, "array"),
+            (Kinds::MAP, "map"),
+            (Kinds::NULL, "null"),
+            (Kinds::FUNCTION, "function"),
+            (Kinds::ARGUMENT, "argument"),
+            (Kinds::OPTIONAL, "optional argument"),
+        ];
+        names
+            .into_iter()
+            .filter(|(kinds, _)| self.intersects(*kinds))
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+}
+
+/// What is known about the stack and variables before an op runs.
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    /// Kinds of the slots known at the top of the stack, top last.
+    slots: Vec<Kinds>,
+    /// How deep the stack is at least, never less than the number of known slots.
+    min: usize,
+    /// How deep the stack is at most, `None` when there is no bound.
+    max: Option<usize>,
+    /// Kinds of variables that are set, anything missing may hold any value.
+    variables: HashMap<BigUint, Kinds>,
+}
+
+impl State {
+    fn push(&mut self, kinds: Kinds) {
+        self.slots.push(kinds);
+        self.min += 1;
+        self.max = self.max.map(|max| max + 1);
+    }
+
+    /// Pops a slot, the stack has to be known to possibly hold one.
+    fn pop(&mut self) -> Kinds {
+        self.min = self.min.saturating_sub(1);
+        self.max = self.max.map(|max| max - 1);
+        self.slots.pop().unwrap_or(Kinds::ANY)
+    }
+
+    /// Pops `n` values at once, which may be far more than the slots that are known.
+    fn pop_many(&mut self, n: usize) {
+        self.min = self.min.saturating_sub(n);
+        self.max = self.max.map(|max| max.saturating_sub(n));
+        self.slots.truncate(self.slots.len().saturating_sub(n));
+    }
+
+    /// The slot `depth` places under the top.
+    fn slot(&mut self, depth: usize) -> Option<&mut Kinds> {
+        let len = self.slots.len();
+        len.checked_sub(depth)
+            .and_then(|i| i.checked_sub(1))
+            .map(|i| &mut self.slots[i])
+    }
+
+    fn variable(&self, index: &BigUint) -> Kinds {
+        self.variables.get(index).copied().unwrap_or(Kinds::VALUE)
+    }
+
+    /// What holds on both paths, with `widen` making a growing depth unbounded.
+    fn join(&self, other: &State, widen: bool) -> State {
+        let len = self.slots.len().min(other.slots.len());
+        let slots = self.slots[self.slots.len() - len..]
+            .iter()
+            .zip(&other.slots[other.slots.len() - len..])
+            .map(|(a, b)| a.union(*b))
+            .collect();
+        let max = match (self.max, other.max) {
+            (Some(a), Some(b)) if widen && b > a => None,
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+        let variables = self
+            .variables
+            .iter()
+            .filter_map(|(index, kinds)| {
+                Some((index.clone(), kinds.union(*other.variables.get(index)?)))
+            })
+            .collect();
+
+        State {
+            slots,
+            min: self.min.min(other.min),
+            max,
+            variables,
+        }
+    }
+}
+
+struct Analyzer<'a> {
+    code: &'a Code,
+    /// Index of the first instruction of the code.
+    offset: usize,
+    /// Whether the code is a function body, whose globals aren't its variables.
+    function: bool,
+    /// Only set on the last pass, once the states are settled.
+    report: bool,
+    problems: Vec<Problem>,
+}
+
+impl Analyzer<'_> {
+    fn index(&self, ptr: usize) -> usize {
+        self.offset + self.code.position(ptr)
+    }
+
+    fn name(&self, ptr: usize) -> &'static str {
+        self.code.ops()[ptr]
+            .opcode()
+            .map_or("unknown instruction", |opcode| opcode.name())
+    }
+
+    fn problem(&mut self, problem: Problem) {
+        if self.report {
+            self.problems.push(problem);
+        }
+    }
+
+    /// Whether `needed` values can be there, reporting an underflow when they never are.
+    fn require(&mut self, state: &State, ptr: usize, needed: usize) -> bool {
+        match state.max {
+            Some(depth) if depth < needed => {
+                self.problem(Problem::StackUnderflow {
+                    index: self.index(ptr),
+                    opcode: self.name(ptr),
+                    needed,
+                    depth,
+                });
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn mismatch(&mut self, ptr: usize, operands: &[Kinds]) {
+        self.problem(Problem::TypeMismatch {
+            index: self.index(ptr),
+            opcode: self.name(ptr),
+            operands: operands
+                .iter()
+                .map(|kinds| kinds.describe())
+                .collect::<Vec<_>>()
+                .join(" and "),
+        });
+    }
+
+    /// Runs `f` on every combination of operand kinds, giving back the kinds of the results,
+    /// or `None` after reporting a mismatch when none of them work.
+    fn apply(
+        &mut self,
+        ptr: usize,
+        operands: &[Kinds],
+        f: impl Fn(&[Value]) -> Option<Value>,
+    ) -> Option<Kinds> {
+        let mut combinations = vec![vec![]];
+        for kinds in operands {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|values: Vec<Value>| {
+                    kinds.values().samples().into_iter().map(move |sample| {
+                        let mut values = values.clone();
+                        values.push(sample);
+                        values
+                    })
+                })
+                .collect();
+        }
+
+        let results = combinations
+            .iter()
+            .filter_map(|values| f(values))
+            .map(|value| Kinds::of(&value))
+            .reduce(Kinds::union);
+        if results.is_none() {
+            self.mismatch(ptr, operands);
+        }
+        results
+    }
+
+    /// The states the op at `ptr` leads to, along with where they continue.
+    fn step(&mut self, ptr: usize, mut state: State) -> Vec<(usize, State)> {
+        let next = ptr + 1;
+        let code = self.code;
+        match &code.ops()[ptr] {
+            Op::Noop => {}
+            Op::Duplicate => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                let kinds = state.pop();
+                state.push(kinds);
+                state.push(kinds);
+            }
+            Op::Pop => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                state.pop();
+            }
+            Op::PushInteger(_) => state.push(Kinds::INTEGER),
+            Op::PushString(_) => state.push(Kinds::STRING),
+            Op::PushFloat(_) => state.push(Kinds::FLOAT),
+            Op::PushFalse | Op::PushTrue => state.push(Kinds::BOOLEAN),
+            Op::PushNull => state.push(Kinds::NULL),
+            Op::IsNull => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                state.pop();
+                state.push(Kinds::BOOLEAN);
+            }
+            Op::PopToVariable(index) => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                let kinds = state.pop().values();
+                state.variables.insert(index.clone(), kinds);
+            }
+            Op::PushVariable(index) => state.push(state.variable(index)),
+            Op::RemoveVariable(index) => {
+                state.variables.remove(index);
+            }
+            Op::PushGlobal(index) => state.push(if self.function {
+                Kinds::VALUE
+            } else {
+                state.variable(index)
+            }),
+            Op::PopToGlobal(index) => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                let kinds = state.pop().values();
+                if !self.function {
+                    state.variables.insert(index.clone(), kinds);
+                }
+            }
+            Op::MakeArgument | Op::MakeOptional(_) => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                state.pop();
+                state.push(match code.ops()[ptr] {
+                    Op::MakeArgument => Kinds::ARGUMENT,
+                    _ => Kinds::OPTIONAL,
+                });
+            }
+            Op::Jump(target) => return vec![(*target, state)],
+            Op::JumpIfFalse(target) | Op::JumpIfTrue(target) => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                state.pop();
+                return vec![(*target, state.clone()), (next, state)];
+            }
+            // anything can be compared for equality
+            Op::Equal | Op::NotEqual => {
+                if !self.require(&state, ptr, 2) {
+                    return vec![];
+                }
+                state.pop();
+                state.pop();
+                state.push(Kinds::BOOLEAN);
+            }
+            Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual => {
+                if !self.require(&state, ptr, 2) {
+                    return vec![];
+                }
+                let operands = [state.pop(), state.pop()];
+                // marked values only compare with values marked the same way
+                if operands.iter().all(|kinds| kinds.within(Kinds::VALUE))
+                    && self
+                        .apply(ptr, &operands, |values| {
+                            values[0]
+                                .partial_cmp(&values[1])
+                                .map(|_| Value::Boolean(true))
+                        })
+                        .is_none()
+                {
+                    return vec![];
+                }
+                state.push(Kinds::BOOLEAN);
+            }
+            Op::Index => {
+                if !self.require(&state, ptr, 2) {
+                    return vec![];
+                }
+                let index = state.pop();
+                let container = state.slot(0).map_or(Kinds::ANY, |kinds| *kinds);
+                let mut kinds = None;
+                // any unmarked value but a float or function may be a key
+                if container.intersects(Kinds::MAP)
+                    && index.intersects(Kinds::VALUE)
+                    && !index.within(Kinds::FLOAT.union(Kinds::FUNCTION))
+                {
+                    kinds = Some(Kinds::VALUE);
+                }
+                if index.intersects(Kinds::INTEGER) {
+                    if container.intersects(Kinds::ARRAY) {
+                        kinds = Some(Kinds::VALUE);
+                    }
+                    if container.intersects(Kinds::STRING) {
+                        let character = Kinds::INTEGER.union(Kinds::NULL);
+                        kinds = Some(kinds.map_or(character, |k| k.union(character)));
+                    }
+                }
+                match kinds {
+                    Some(kinds) => state.push(kinds),
+                    None => {
+                        self.mismatch(ptr, &[index, container]);
+                        return vec![];
+                    }
+                }
+            }
+            Op::PushArray(length) => {
+                if !self.require(&state, ptr, *length) {
+                    return vec![];
+                }
+                state.pop_many(*length);
+                state.push(Kinds::ARRAY);
+            }
+            Op::PushMap(length) => {
+                if !self.require(&state, ptr, length.saturating_mul(2)) {
+                    return vec![];
+                }
+                state.pop_many(length.saturating_mul(2));
+                state.push(Kinds::MAP);
+            }
+            Op::SpreadArray => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                let kinds = state.pop();
+                if !kinds.intersects(Kinds::ARRAY) {
+                    self.mismatch(ptr, &[kinds]);
+                    return vec![];
+                }
+                state.slots.clear();
+                state.max = None;
+            }
+            Op::Negate | Op::Not => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                let operand = state.pop();
+                let negate = matches!(code.ops()[ptr], Op::Negate);
+                let Some(kinds) = self.apply(ptr, &[operand], |values| {
+                    if negate {
+                        arithmetic::negate(values[0].clone()).ok()
+                    } else {
+                        arithmetic::not(values[0].clone()).ok()
+                    }
+                }) else {
+                    return vec![];
+                };
+                state.push(kinds);
+            }
+            op @ (Op::Add
+            | Op::Subtract
+            | Op::Multiply
+            | Op::Divide
+            | Op::Remainder
+            | Op::Power
+            | Op::FloorDivide
+            | Op::BitAnd
+            | Op::BitOr
+            | Op::BitXor
+            | Op::ShiftLeft
+            | Op::ShiftRight
+            | Op::And
+            | Op::Or) => {
+                if !self.require(&state, ptr, 2) {
+                    return vec![];
+                }
+                let Some(operator) = BinaryOperator::of(op) else {
+                    return vec![];
+                };
+                let operands = [state.pop(), state.pop()];
+                let Some(kinds) = self.apply(ptr, &operands, |values| {
+                    arithmetic::binary(operator, values[0].clone(), values[1].clone()).ok()
+                }) else {
+                    return vec![];
+                };
+                state.push(kinds);
+            }
+            Op::Swap(i1, i2) => {
+                if !self.require(&state, ptr, i1.max(i2).saturating_add(1)) {
+                    return vec![];
+                }
+                let k1 = state.slot(*i1).map_or(Kinds::ANY, |kinds| *kinds);
+                let k2 = state.slot(*i2).map_or(Kinds::ANY, |kinds| *kinds);
+                if let Some(slot) = state.slot(*i1) {
+                    *slot = k2;
+                }
+                if let Some(slot) = state.slot(*i2) {
+                    *slot = k1;
+                }
+            }
+            Op::DefineFunction(_) => state.push(Kinds::USER),
+            Op::Return => {
+                self.require(&state, ptr, 1);
+                return vec![];
+            }
+            Op::Call => {
+                if !self.require(&state, ptr, 1) {
+                    return vec![];
+                }
+                return self.call(ptr, state);
+            }
+            Op::Invalid => return vec![],
+        }
+        vec![(next, state)]
+    }
+
+    /// A call pops arguments until the first unmarked function, which may give back a value.
+    fn call(&mut self, ptr: usize, mut state: State) -> Vec<(usize, State)> {
+        let callee = state
+            .slots
+            .iter()
+            .rev()
+            .position(|kinds| kinds.intersects(Kinds::FUNCTION));
+
+        let function = match callee {
+            Some(depth) if state.slots[state.slots.len() - 1 - depth].within(Kinds::FUNCTION) => {
+                for _ in 0..depth {
+                    state.pop();
+                }
+                state.pop()
+            }
+            None if state.max == Some(state.slots.len()) => {
+                self.problem(Problem::NotCallable {
+                    index: self.index(ptr),
+                });
+                return vec![];
+            }
+            // the function could be further down
+            _ => {
+                let popped = callee.unwrap_or(state.slots.len()) + 1;
+                state.slots.clear();
+                state.min = 0;
+                state.max = state.max.map(|max| max.saturating_sub(popped));
+                Kinds::FUNCTION
+            }
+        };
+
+        if function.intersects(Kinds::USER) && !self.function {
+            state.variables.clear();
+        }
+        let mut returned = state.clone();
+        returned.push(Kinds::VALUE);
+        let state = match (
+            function.intersects(Kinds::SILENT.union(Kinds::USER)),
+            function.intersects(Kinds::RETURNING.union(Kinds::USER)),
+        ) {
+            (true, true) => state.join(&returned, false),
+            (false, _) => returned,
+            (true, false) => state,
+        };
+        vec![(ptr + 1, state)]
+    }
+
+    fn run(&mut self, entry: State) {
+        let len = self.code.ops().len();
+        let mut states: Vec<Option<State>> = vec![None; len];
+        let mut visits = vec![0; len];
+        let mut pending = VecDeque::new();
+        if len > 0 {
+            states[0] = Some(entry);
+            pending.push_back(0);
+        }
+
+        while let Some(ptr) = pending.pop_front() {
+            let Some(state) = states[ptr].clone() else {
+                continue;
+            };
+            for (next, state) in self.step(ptr, state) {
+                let Some(slot) = states.get_mut(next) else {
+                    continue;
+                };
+                let joined = match slot {
+                    Some(old) => old.join(&state, visits[next] >= WIDEN_AFTER),
+                    None => state,
+                };
+                if slot.as_ref() != Some(&joined) {
+                    *slot = Some(joined);
+                    visits[next] += 1;
+                    if !pending.contains(&next) {
+                        pending.push_back(next);
+                    }
+                }
+            }
+        }
+
+        self.report = true;
+        for (ptr, state) in states.into_iter().enumerate() {
+            if let Some(state) = state {
+                self.step(ptr, state);
+            }
+        }
+    }
+}
+
+fn analyze_block(code: &Code, offset: usize, entry: Option<&Function>) -> Vec<Problem> {
+    let mut state = State {
+        slots: Vec::new(),
+        min: 0,
+        max: Some(0),
+        variables: HashMap::new(),
+    };
+    match entry {
+        // arguments sit on top of whatever the caller left
+        Some(function) => {
+            state.slots = vec![Kinds::VALUE; function.arity()];
+            if function.varargs() {
+                state.slots.push(Kinds::ARRAY);
+            }
+            state.min = state.slots.len();
+            state.max = None;
+        }
+        None => {
+            for (index, value) in Memory::new().iter() {
+                state.variables.insert(index.clone(), Kinds::of(value));
+            }
+        }
+    }
+
+    let mut analyzer = Analyzer {
+        code,
+        offset,
+        function: entry.is_some(),
+        report: false,
+        problems: Vec::new(),
+    };
+    analyzer.run(state);
+
+    let mut problems = analyzer.problems;
+    for (ptr, op) in code.ops().iter().enumerate() {
+        if let Op::DefineFunction(function) = op {
+            problems.extend(analyze_block(
+                function.code(),
+                offset + code.position(ptr) + 4,
+                Some(function),
+            ));
+        }
+    }
+    problems
+}
+
+/// Follows every path through `code` from an empty stack and reports the problems
+/// that are certain to happen when a path reaches them, ordered by index.
+pub fn analyze(code: &Code) -> Vec<Problem> {
+    let mut problems = analyze_block(code, 0, None);
+    problems.sort_by_key(Problem::index);
+    problems
+}
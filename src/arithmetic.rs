@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+
+use anyhow::Result;
+use num_bigint::{BigInt, BigUint};
+use num_traits::{FromPrimitive, One, Pow, Signed, ToPrimitive, Zero};
+
+use crate::{
+    interpreter::{RuntimeError, MAX_REPEATED_LENGTH},
+    op::Op,
+    value::Value,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    FloorDivide,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+}
+
+impl BinaryOperator {
+    /// The operator `op` applies, `None` when it isn't a binary operator.
+    pub(crate) fn of(op: &Op) -> Option<BinaryOperator> {
+        Some(match op {
+            Op::Add => BinaryOperator::Add,
+            Op::Subtract => BinaryOperator::Subtract,
+            Op::Multiply => BinaryOperator::Multiply,
+            Op::Divide => BinaryOperator::Divide,
+            Op::Remainder => BinaryOperator::Remainder,
+            Op::Power => BinaryOperator::Power,
+            Op::FloorDivide => BinaryOperator::FloorDivide,
+            Op::BitAnd => BinaryOperator::BitAnd,
+            Op::BitOr => BinaryOperator::BitOr,
+            Op::BitXor => BinaryOperator::BitXor,
+            Op::ShiftLeft => BinaryOperator::ShiftLeft,
+            Op::ShiftRight => BinaryOperator::ShiftRight,
+            Op::And => BinaryOperator::And,
+            Op::Or => BinaryOperator::Or,
+            _ => return None,
+        })
+    }
+}
+
+/// Whether the comparison `op` holds when top compares to top2 as `ordering`,
+/// `None` when `op` isn't an ordering comparison.
+pub(crate) fn compare(op: &Op, ordering: Ordering) -> Option<bool> {
+    Some(match op {
+        Op::Less => ordering == Ordering::Less,
+        Op::LessEqual => ordering != Ordering::Greater,
+        Op::Greater => ordering == Ordering::Greater,
+        Op::GreaterEqual => ordering != Ordering::Less,
+        _ => return None,
+    })
+}
+
+/// Whether `v1` and `v2` are equal, integers and floats by their value. Values that can't be
+/// ordered, like maps, functions or values of different kinds, are equal when they're the same.
+pub(crate) fn equal(v1: &Value, v2: &Value) -> bool {
+    match v1.partial_cmp(v2) {
+        Some(ordering) => ordering == Ordering::Equal,
+        None => v1 == v2,
+    }
+}
+
+/// Operand of an arithmetic instruction, booleans count as 0 and 1.
+enum Number {
+    Integer(BigInt),
+    Float(f64),
+}
+
+impl TryFrom<Value> for Number {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(Number::Integer((b as u8).into())),
+            Value::Integer(i) => Ok(Number::Integer(i)),
+            Value::Float(fl) => Ok(Number::Float(fl)),
+            _ => Err(RuntimeError::InvalidInstruction),
+        }
+    }
+}
+
+impl Number {
+    fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(int) => int.is_zero(),
+            Number::Float(fl) => *fl == 0.0,
+        }
+    }
+}
+
+fn to_float(int: &BigInt) -> f64 {
+    int.to_f64().unwrap_or(f64::NAN)
+}
+
+/// How many times a string or array gets repeated, none for negative counts.
+fn count(int: &BigInt) -> Result<usize> {
+    if int.is_negative() {
+        return Ok(0);
+    }
+    Ok(usize::try_from(int).map_err(|_| RuntimeError::InvalidInstruction)?)
+}
+
+/// Length of `len` repeated `count` times, failing above [`MAX_REPEATED_LENGTH`].
+fn repeated_length(len: usize, count: &BigInt) -> Result<usize> {
+    let count = if count.is_negative() {
+        BigInt::zero()
+    } else {
+        count.clone()
+    };
+    let total = count * len;
+    match usize::try_from(total) {
+        Ok(total) if total <= MAX_REPEATED_LENGTH => Ok(total),
+        _ => Err(RuntimeError::TooLong.into()),
+    }
+}
+
+/// How far an integer gets shifted, shifting by a negative amount is an error.
+fn shift(int: BigInt) -> Result<usize> {
+    if int.is_negative() {
+        return Err(RuntimeError::NegativeShift(int).into());
+    }
+    Ok(usize::try_from(&int).map_err(|_| RuntimeError::InvalidInstruction)?)
+}
+
+/// Applies `operator` to top (`v1`) and top2 (`v2`).
+///
+/// Strings and arrays concatenate with `+` and repeat with `*`, arrays also lose the
+/// elements of another array with `-`.
+/// `and` and `or` give whichever operand decides the result, like Python does.
+/// Bitwise operators keep booleans booleans and otherwise only take integers.
+/// Integers stay integers, anything involving a float is promoted to a float.
+/// Division truncates towards zero and the remainder takes the sign of `v1`,
+/// floored division rounds down.
+pub(crate) fn binary(operator: BinaryOperator, v1: Value, v2: Value) -> Result<Value> {
+    use BinaryOperator::*;
+
+    let (v1, v2) = match (operator, v1, v2) {
+        (And, v1, v2) => return Ok(if bool::try_from(v1.clone())? { v2 } else { v1 }),
+        (Or, v1, v2) => return Ok(if bool::try_from(v1.clone())? { v1 } else { v2 }),
+        (BitAnd, Value::Boolean(b1), Value::Boolean(b2)) => return Ok(Value::Boolean(b1 & b2)),
+        (BitOr, Value::Boolean(b1), Value::Boolean(b2)) => return Ok(Value::Boolean(b1 | b2)),
+        (BitXor, Value::Boolean(b1), Value::Boolean(b2)) => return Ok(Value::Boolean(b1 ^ b2)),
+        (Add, Value::String(s1), Value::String(s2)) => return Ok(Value::String(s1 + &s2)),
+        (Add, Value::Array(mut a1), Value::Array(a2)) => {
+            a1.extend(a2);
+            return Ok(Value::Array(a1));
+        }
+        (Multiply, Value::String(s), Value::Integer(n))
+        | (Multiply, Value::Integer(n), Value::String(s)) => {
+            if repeated_length(s.len(), &n)? == 0 {
+                return Ok(Value::String(String::new()));
+            }
+            return Ok(Value::String(s.repeat(count(&n)?)));
+        }
+        (Multiply, Value::Array(a), Value::Integer(n))
+        | (Multiply, Value::Integer(n), Value::Array(a)) => {
+            if repeated_length(a.len(), &n)? == 0 {
+                return Ok(Value::Array(Vec::new()));
+            }
+            return Ok(Value::Array(
+                std::iter::repeat_n(a, count(&n)?).flatten().collect(),
+            ));
+        }
+        (Subtract, Value::Array(a1), Value::Array(a2)) => {
+            return Ok(Value::Array(
+                a1.into_iter().filter(|v| !a2.contains(v)).collect(),
+            ))
+        }
+        (_, v1, v2) => (v1, v2),
+    };
+
+    let (v1, v2) = (Number::try_from(v1)?, Number::try_from(v2)?);
+    if matches!(operator, Divide | Remainder | FloorDivide) && v2.is_zero() {
+        return Err(RuntimeError::DivisionByZero.into());
+    }
+
+    Ok(match (v1, v2) {
+        (Number::Integer(v1), Number::Integer(v2)) => Value::Integer(match operator {
+            Add => v1 + v2,
+            Subtract => v1 - v2,
+            Multiply => v1 * v2,
+            Divide => v1 / v2,
+            Remainder => v1 % v2,
+            Power => {
+                v1.pow(BigUint::try_from(&v2).map_err(|_| RuntimeError::NegativeExponent(v2))?)
+            }
+            FloorDivide => {
+                let quotient = &v1 / &v2;
+                if !(&v1 % &v2).is_zero() && v1.is_negative() != v2.is_negative() {
+                    quotient - BigInt::one()
+                } else {
+                    quotient
+                }
+            }
+            BitAnd => v1 & v2,
+            BitOr => v1 | v2,
+            BitXor => v1 ^ v2,
+            ShiftLeft => v1 << shift(v2)?,
+            ShiftRight => v1 >> shift(v2)?,
+            And | Or => unreachable!(),
+        }),
+        (v1, v2) => {
+            let (v1, v2) = match (v1, v2) {
+                (Number::Float(v1), Number::Float(v2)) => (v1, v2),
+                (Number::Integer(v1), Number::Float(v2)) => (to_float(&v1), v2),
+                (Number::Float(v1), Number::Integer(v2)) => (v1, to_float(&v2)),
+                (Number::Integer(_), Number::Integer(_)) => unreachable!(),
+            };
+            Value::Float(match operator {
+                Add => v1 + v2,
+                Subtract => v1 - v2,
+                Multiply => v1 * v2,
+                Divide => v1 / v2,
+                Remainder => v1 % v2,
+                Power => v1.powf(v2),
+                FloorDivide => (v1 / v2).floor(),
+                BitAnd | BitOr | BitXor | ShiftLeft | ShiftRight => {
+                    return Err(RuntimeError::InvalidInstruction.into())
+                }
+                And | Or => unreachable!(),
+            })
+        }
+    })
+}
+
+/// -top
+pub(crate) fn negate(v: Value) -> Result<Value> {
+    Ok(match Number::try_from(v)? {
+        Number::Integer(v) => Value::Integer(-v),
+        Number::Float(v) => Value::Float(-v),
+    })
+}
+
+/// !top, logical for booleans and bitwise for integers
+pub(crate) fn not(v: Value) -> Result<Value> {
+    match v {
+        Value::Boolean(v) => Ok(Value::Boolean(!v)),
+        Value::Integer(v) => Ok(Value::Integer(!v)),
+        _ => Err(RuntimeError::InvalidInstruction.into()),
+    }
+}
+
+/// Converts a float to an integer, truncating towards zero.
+pub(crate) fn truncate(fl: f64) -> Option<BigInt> {
+    BigInt::from_f64(fl.trunc())
+}
@@ -0,0 +1,392 @@
+//! Assembler turning mnemonic source into path code.
+//!
+//! ```text
+//! ; prints the numbers from 0 upwards
+//!         push 0
+//! loop:   dup
+//!         load PrintLn
+//!         swap 0 1
+//!         call
+//!         push 1
+//!         add
+//!         jump loop
+//! ```
+//!
+//! Every line holds an optional `label:` and one instruction, `;` starts a comment.
+//! `push` takes an integer, float, `true`, `false`, `null` or a double quoted string.
+//! Variables can be numbers or names, built-in functions and Args keep their names from the spec
+//! and any other name gets the next free identifier.
+//! `func <arity> [varargs]` starts a function body that runs until the matching `end`,
+//! labels inside it are local to the body.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{FromPrimitive, One};
+use thiserror::Error;
+
+use crate::{
+    instruction::Instruction,
+    memory::Memory,
+    opcode::{Opcode, Operand},
+    program::Program,
+};
+
+#[derive(Debug, Error)]
+pub enum AssembleError {
+    #[error("Line {line}: unknown mnemonic {mnemonic:?}")]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[error("Line {line}: {mnemonic} takes {expected} operands, got {got}")]
+    InvalidNumberOfOperands {
+        line: usize,
+        mnemonic: String,
+        expected: usize,
+        got: usize,
+    },
+    #[error("Line {line}: invalid operand {operand}")]
+    InvalidOperand { line: usize, operand: String },
+    #[error("Line {line}: unknown label {label:?}")]
+    UnknownLabel { line: usize, label: String },
+    #[error("Line {line}: label {label:?} is already defined")]
+    DuplicateLabel { line: usize, label: String },
+    #[error("Line {line}: unterminated string")]
+    UnterminatedString { line: usize },
+    #[error("Line {line}: func is never closed with end")]
+    UnclosedFunction { line: usize },
+    #[error("Line {line}: end without func")]
+    UnexpectedEnd { line: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    String(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => word.clone(),
+            Token::String(string) => format!("{string:?}"),
+        }
+    }
+}
+
+fn tokenize(text: &str, line: usize) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ';' => break,
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some('0') => '\0',
+                            Some(c) => c,
+                            None => return Err(AssembleError::UnterminatedString { line }.into()),
+                        }),
+                        Some(c) => string.push(c),
+                        None => return Err(AssembleError::UnterminatedString { line }.into()),
+                    }
+                }
+                tokens.push(Token::String(string));
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Assembler {
+    variables: HashMap<String, BigUint>,
+    next_variable: BigUint,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        let memory = Memory::new();
+        let variables = memory
+            .names()
+            .into_iter()
+            .map(|(index, name)| (name, index))
+            .collect();
+        let mut next_variable = BigUint::default();
+        for (index, _) in memory.iter() {
+            if *index >= next_variable {
+                next_variable = index + BigUint::one();
+            }
+        }
+
+        Assembler {
+            variables,
+            next_variable,
+        }
+    }
+
+    fn identifier(&mut self, token: &Token, line: usize) -> Result<BigUint> {
+        let invalid = || AssembleError::InvalidOperand {
+            line,
+            operand: token.describe(),
+        };
+
+        let Token::Word(word) = token else {
+            return Err(invalid().into());
+        };
+        if let Ok(index) = word.parse() {
+            return Ok(index);
+        }
+        if !word.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return Err(invalid().into());
+        }
+
+        if let Some(index) = self.variables.get(word) {
+            return Ok(index.clone());
+        }
+        let index = self.next_variable.clone();
+        self.next_variable += BigUint::one();
+        self.variables.insert(word.clone(), index.clone());
+        Ok(index)
+    }
+
+    /// Assembles lines until the `end` closing the function opened on line `opened`,
+    /// or until the source runs out when `opened` is `None`.
+    fn block(
+        &mut self,
+        lines: &mut dyn Iterator<Item = (usize, &str)>,
+        opened: Option<usize>,
+    ) -> Result<Vec<Instruction>> {
+        let mut instructions = Vec::new();
+        let mut labels = HashMap::new();
+        let mut jumps = Vec::new();
+        let mut closed = false;
+
+        while let Some((line, text)) = lines.next() {
+            let mut tokens = tokenize(text, line)?.into_iter().peekable();
+
+            while let Some(Token::Word(word)) = tokens.peek() {
+                let Some(label) = word.strip_suffix(':') else {
+                    break;
+                };
+                if labels
+                    .insert(label.to_owned(), instructions.len())
+                    .is_some()
+                {
+                    return Err(AssembleError::DuplicateLabel {
+                        line,
+                        label: label.to_owned(),
+                    }
+                    .into());
+                }
+                tokens.next();
+            }
+
+            let mnemonic = match tokens.next() {
+                Some(Token::Word(mnemonic)) => mnemonic,
+                Some(token) => {
+                    return Err(AssembleError::UnknownMnemonic {
+                        line,
+                        mnemonic: token.describe(),
+                    }
+                    .into())
+                }
+                None => continue,
+            };
+            let operands: Vec<Token> = tokens.collect();
+            let expect_operands = |expected: usize| {
+                if operands.len() == expected {
+                    Ok(())
+                } else {
+                    Err(AssembleError::InvalidNumberOfOperands {
+                        line,
+                        mnemonic: mnemonic.clone(),
+                        expected,
+                        got: operands.len(),
+                    })
+                }
+            };
+
+            match mnemonic.as_str() {
+                "end" => {
+                    expect_operands(0)?;
+                    if opened.is_none() {
+                        return Err(AssembleError::UnexpectedEnd { line }.into());
+                    }
+                    closed = true;
+                    break;
+                }
+                "func" => {
+                    let (arity, varargs) = match operands.as_slice() {
+                        [arity] => (number(arity, line)?, false),
+                        [arity, Token::Word(word)] if word == "varargs" => {
+                            (number(arity, line)?, true)
+                        }
+                        [_, token] => {
+                            return Err(AssembleError::InvalidOperand {
+                                line,
+                                operand: token.describe(),
+                            }
+                            .into())
+                        }
+                        _ => {
+                            return Err(AssembleError::InvalidNumberOfOperands {
+                                line,
+                                mnemonic,
+                                expected: 1,
+                                got: operands.len(),
+                            }
+                            .into())
+                        }
+                    };
+                    let body = self.block(lines, Some(line))?;
+
+                    instructions.push(Opcode::DefineFunction.code().to_vec().into());
+                    instructions.push(varargs.into());
+                    instructions.push((&arity).into());
+                    instructions.push(body.len().into());
+                    instructions.extend(body);
+                }
+                "push" => {
+                    expect_operands(1)?;
+                    instructions.extend(literal(&operands[0], line)?);
+                }
+                _ => {
+                    let opcode = Opcode::ALL
+                        .into_iter()
+                        .find(|opcode| opcode.mnemonic() == mnemonic)
+                        .ok_or_else(|| AssembleError::UnknownMnemonic {
+                            line,
+                            mnemonic: mnemonic.clone(),
+                        })?;
+                    expect_operands(opcode.operands().len())?;
+
+                    instructions.push(opcode.code().to_vec().into());
+                    for (operand, token) in opcode.operands().iter().zip(&operands) {
+                        instructions.push(match operand {
+                            Operand::Identifier => (&self.identifier(token, line)?).into(),
+                            Operand::Number => (&number(token, line)?).into(),
+                            Operand::Target => {
+                                jumps.push((instructions.len(), token.clone(), line));
+                                Instruction::default()
+                            }
+                            _ => unreachable!("{opcode:?} is assembled separately"),
+                        });
+                    }
+                }
+            }
+        }
+
+        if let (Some(line), false) = (opened, closed) {
+            return Err(AssembleError::UnclosedFunction { line }.into());
+        }
+
+        for (at, token, line) in jumps {
+            let target = match &token {
+                Token::Word(word) => match labels.get(word) {
+                    Some(target) => BigUint::from(*target),
+                    None => word.parse().map_err(|_| AssembleError::UnknownLabel {
+                        line,
+                        label: word.clone(),
+                    })?,
+                },
+                Token::String(_) => {
+                    return Err(AssembleError::InvalidOperand {
+                        line,
+                        operand: token.describe(),
+                    }
+                    .into())
+                }
+            };
+            instructions[at] = (&target).into();
+        }
+
+        Ok(instructions)
+    }
+}
+
+fn number(token: &Token, line: usize) -> Result<BigUint> {
+    match token {
+        Token::Word(word) => word.parse().ok(),
+        Token::String(_) => None,
+    }
+    .ok_or_else(|| {
+        AssembleError::InvalidOperand {
+            line,
+            operand: token.describe(),
+        }
+        .into()
+    })
+}
+
+/// The instructions pushing the literal in `token`.
+fn literal(token: &Token, line: usize) -> Result<Vec<Instruction>> {
+    let invalid = || AssembleError::InvalidOperand {
+        line,
+        operand: token.describe(),
+    };
+
+    let word = match token {
+        Token::String(string) => {
+            let mut instructions = vec![
+                Opcode::PushString.code().to_vec().into(),
+                string.chars().count().into(),
+            ];
+            instructions.extend(string.chars().map(Instruction::from));
+            return Ok(instructions);
+        }
+        Token::Word(word) => word,
+    };
+
+    match word.as_str() {
+        "true" => return Ok(vec![Opcode::PushTrue.code().to_vec().into()]),
+        "false" => return Ok(vec![Opcode::PushFalse.code().to_vec().into()]),
+        "null" => return Ok(vec![Opcode::PushNull.code().to_vec().into()]),
+        _ => {}
+    }
+
+    if let Ok(int) = word.parse::<BigInt>() {
+        return Ok(vec![
+            Opcode::PushInteger.code().to_vec().into(),
+            (int.sign() == Sign::Plus).into(),
+            int.magnitude().into(),
+        ]);
+    }
+
+    match word.parse::<f64>() {
+        Ok(float) if float.is_finite() => Ok(vec![
+            Opcode::PushFloat.code().to_vec().into(),
+            float.is_sign_positive().into(),
+            (&BigUint::from_f64(float.abs().trunc()).ok_or_else(invalid)?).into(),
+            Instruction::from_fraction(float.abs().fract()),
+        ]),
+        _ => Err(invalid().into()),
+    }
+}
+
+/// Assembles mnemonic `source` into a program, `to_string` gives its path.
+pub fn assemble(source: &str) -> Result<Program> {
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line));
+    Ok(Assembler::new().block(&mut lines, None)?.into())
+}
@@ -0,0 +1,139 @@
+//! Static validation of parsed instructions, finding what would otherwise only fail
+//! once the interpreter reaches it.
+
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use thiserror::Error;
+
+use crate::{
+    instruction::Instruction,
+    opcode::{Opcode, Operand},
+};
+
+/// A structural problem, indices are counted like jumps at the top level do
+/// while jump targets are kept as written.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Problem {
+    #[error("Instruction {index}: unknown instruction")]
+    UnknownInstruction { index: usize },
+    #[error("Instruction {index}: {opcode} is missing operands")]
+    MissingOperands { index: usize, opcode: &'static str },
+    #[error("Instruction {index}: {number} is too large to be a count")]
+    NumberTooLarge { index: usize, number: BigUint },
+    #[error("Instruction {index}: not a Unicode character")]
+    InvalidCharacter { index: usize },
+    #[error("Instruction {index}: jump target {target} is past the end at {end}")]
+    TargetPastEnd {
+        index: usize,
+        target: BigUint,
+        end: usize,
+    },
+    #[error("Instruction {index}: jump target {target} is inside the operands of instruction {instruction}")]
+    TargetInsideInstruction {
+        index: usize,
+        target: usize,
+        instruction: usize,
+    },
+}
+
+impl Problem {
+    pub fn index(&self) -> usize {
+        match self {
+            Problem::UnknownInstruction { index }
+            | Problem::MissingOperands { index, .. }
+            | Problem::NumberTooLarge { index, .. }
+            | Problem::InvalidCharacter { index, .. }
+            | Problem::TargetPastEnd { index, .. }
+            | Problem::TargetInsideInstruction { index, .. } => *index,
+        }
+    }
+}
+
+/// Checks the instructions of a block whose first instruction is at `offset`,
+/// function bodies are blocks of their own since their jumps are relative to them.
+fn check_block(instructions: &[Instruction], offset: usize, problems: &mut Vec<Problem>) {
+    let mut starts = Vec::new();
+    let mut jumps = Vec::new();
+
+    let mut at = 0;
+    while at < instructions.len() {
+        starts.push(at);
+        let index = offset + at;
+        let Some(opcode) = Opcode::decode(&instructions[at]) else {
+            problems.push(Problem::UnknownInstruction { index });
+            at += 1;
+            continue;
+        };
+
+        let width = opcode.width(instructions, at);
+        if width > instructions.len() - at {
+            problems.push(Problem::MissingOperands {
+                index,
+                opcode: opcode.name(),
+            });
+            break;
+        }
+
+        let mut next = at + 1;
+        for operand in opcode.operands() {
+            let instruction = &instructions[next];
+            next += 1;
+
+            match operand {
+                Operand::Number => {
+                    let number = BigUint::from(instruction);
+                    if number.to_usize().is_none() {
+                        problems.push(Problem::NumberTooLarge { index, number });
+                    }
+                }
+                Operand::Target => jumps.push((index, BigUint::from(instruction))),
+                Operand::String => {
+                    let length: usize = instruction.into();
+                    for (i, c) in instructions[next..next + length].iter().enumerate() {
+                        if Into::<anyhow::Result<char>>::into(c).is_err() {
+                            problems.push(Problem::InvalidCharacter {
+                                index: offset + next + i,
+                            });
+                        }
+                    }
+                    next += length;
+                }
+                Operand::Body => {
+                    let length: usize = instruction.into();
+                    check_block(&instructions[next..next + length], offset + next, problems);
+                    next += length;
+                }
+                Operand::Boolean | Operand::Identifier | Operand::Fraction => {}
+            }
+        }
+        at += width;
+    }
+
+    for (index, target) in jumps {
+        match target.to_usize() {
+            Some(to) if to == instructions.len() => {}
+            Some(to) if to < instructions.len() => {
+                if let Err(after) = starts.binary_search(&to) {
+                    problems.push(Problem::TargetInsideInstruction {
+                        index,
+                        target: to,
+                        instruction: offset + starts[after - 1],
+                    });
+                }
+            }
+            _ => problems.push(Problem::TargetPastEnd {
+                index,
+                target,
+                end: instructions.len(),
+            }),
+        }
+    }
+}
+
+/// Walks `instructions` once and reports every structural problem, ordered by index.
+pub fn check(instructions: &[Instruction]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    check_block(instructions, 0, &mut problems);
+    problems.sort_by_key(Problem::index);
+    problems
+}
@@ -0,0 +1,197 @@
+//! Step debugger pausing a running program to look at its stack and memory.
+//!
+//! ```text
+//! at instruction 0: push variable <1>
+//! (debug) break 9
+//! (debug) continue
+//! breakpoint at instruction 9: call function
+//! (debug) stack
+//! [<function varargs arity=0 built-in>, "Hello, World!"]
+//! ```
+//!
+//! Instruction indices are counted like jumps at the top level do, inside a function call
+//! they count from the start of its body, so breakpoints only apply at the top level.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+};
+
+use anyhow::Result;
+use num_bigint::BigUint;
+use thiserror::Error;
+
+use crate::{
+    interpreter::{Hook, Interpreter},
+    op::{Code, Op},
+    repl::{show_stack, show_variables},
+};
+
+#[derive(Debug, Error)]
+pub enum DebugError {
+    #[error("Stopped from the debugger")]
+    Quit,
+}
+
+const HELP: &str = "\
+step (s)              run the next instruction
+next (n)              run the next instruction, running calls to the end
+continue (c)          run until a breakpoint or watchpoint
+break (b) INDEX       pause before the instruction at INDEX
+delete (d) INDEX      remove the breakpoint at INDEX
+watch (w) VARIABLE    pause before VARIABLE is popped to or removed
+unwatch VARIABLE      remove the watchpoint on VARIABLE
+where                 show the next instruction again
+stack                 show the stack, top last
+memory (m)            show the global variables and those of the current call
+quit (q)              stop the program";
+
+/// When to pause next, besides breakpoints and watchpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Step,
+    /// Pauses once no deeper than the given number of calls.
+    StepOver(usize),
+    Continue,
+}
+
+/// A [`Hook`] reading commands from stdin whenever it pauses, which it does before the first
+/// instruction.
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<BigUint>,
+    mode: Mode,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            mode: Mode::Step,
+        }
+    }
+
+    /// Why to pause before the op at `ptr`, if at all.
+    fn reason(&self, code: &Code, ptr: usize, depth: usize) -> Option<String> {
+        if let Op::PopToVariable(index) | Op::RemoveVariable(index) | Op::PopToGlobal(index) =
+            &code.ops()[ptr]
+        {
+            if self.watchpoints.contains(index) {
+                return Some(format!("watchpoint on variable {index} "));
+            }
+        }
+        if depth == 0 && self.breakpoints.contains(&code.position(ptr)) {
+            return Some("breakpoint ".to_owned());
+        }
+        match self.mode {
+            Mode::Step => Some(String::new()),
+            Mode::StepOver(over) if depth <= over => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    /// Runs commands until one resumes the program.
+    fn prompt(&mut self, interpreter: &Interpreter, code: &Code, ptr: usize) -> Result<()> {
+        let depth = interpreter.memory().depth();
+        loop {
+            print!("(debug) ");
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input)? == 0 {
+                println!();
+                return self.resume(Mode::Continue);
+            }
+            let mut words = input.split_whitespace();
+            let command = words.next().unwrap_or_default();
+            let argument = words.next();
+            if words.next().is_some() {
+                println!("{HELP}");
+                continue;
+            }
+
+            match (command, argument) {
+                ("s" | "step", None) => return self.resume(Mode::Step),
+                ("n" | "next", None) => return self.resume(Mode::StepOver(depth)),
+                ("c" | "continue", None) => return self.resume(Mode::Continue),
+                ("q" | "quit", None) => return Err(DebugError::Quit.into()),
+                ("where", None) => println!("at {}", describe(code, ptr, depth)),
+                ("stack", None) => println!("{}", show_stack(interpreter.stack())),
+                ("m" | "memory", None) => {
+                    print!("{}", show_variables(interpreter.memory().iter()));
+                    if depth > 0 {
+                        println!("locals of the current call:");
+                        print!("{}", show_variables(interpreter.memory().locals()));
+                    }
+                }
+                ("b" | "break", Some(index)) => match index.parse() {
+                    Ok(index) => {
+                        self.breakpoints.insert(index);
+                    }
+                    Err(_) => println!("Not an instruction index: {index}"),
+                },
+                ("d" | "delete", Some(index)) => match index.parse() {
+                    Ok(index) if self.breakpoints.remove(&index) => {}
+                    Ok(index) => println!("No breakpoint at {index}"),
+                    Err(_) => println!("Not an instruction index: {index}"),
+                },
+                ("w" | "watch", Some(index)) => match index.parse() {
+                    Ok(index) => {
+                        self.watchpoints.insert(index);
+                    }
+                    Err(_) => println!("Not a variable identifier: {index}"),
+                },
+                ("unwatch", Some(index)) => match index.parse() {
+                    Ok(index) if self.watchpoints.remove(&index) => {}
+                    Ok(index) => println!("No watchpoint on {index}"),
+                    Err(_) => println!("Not a variable identifier: {index}"),
+                },
+                ("", None) => {}
+                _ => println!("{HELP}"),
+            }
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) -> Result<()> {
+        self.mode = mode;
+        Ok(())
+    }
+}
+
+/// The decoded op at `ptr`, with where it is.
+fn describe(code: &Code, ptr: usize, depth: usize) -> String {
+    let index = code.position(ptr);
+    let mut text = format!("instruction {index}: ");
+    match code.ops()[ptr].opcode() {
+        Some(opcode) => {
+            text.push_str(opcode.name());
+            for operand in opcode.format_operands(code.instructions(), index) {
+                text.push_str(&format!(" <{operand}>"));
+            }
+        }
+        None => text.push_str("unknown instruction"),
+    }
+    if depth > 0 {
+        text.push_str(&format!(" ({depth} call(s) deep)"));
+    }
+    text
+}
+
+impl Hook for Debugger {
+    fn before(&mut self, interpreter: &Interpreter, code: &Code, ptr: usize) -> Result<()> {
+        let depth = interpreter.memory().depth();
+        let Some(reason) = self.reason(code, ptr, depth) else {
+            return Ok(());
+        };
+        println!("{reason}at {}", describe(code, ptr, depth));
+        self.prompt(interpreter, code, ptr)
+    }
+}
@@ -0,0 +1,188 @@
+//! Disassembler producing annotated listings like the breakdowns in `examples/`.
+//!
+//! ```text
+//!  0  ././/        push integer <false> <0>
+//!  3  .//          duplicate top of stack    <--+
+//!  4  .././/       push variable <1 PrintLn>    |
+//!  6  ./././././/  swap stack values <0> <1>    |
+//!  9  ././..//     call function                |
+//! 10  ././/        push integer <true> <1>      |
+//! 13  .././././/   top + top2                   |
+//! 14  ../././/     jump to instruction <3>   ---+
+//! ```
+
+use std::collections::HashMap;
+
+use num_bigint::BigUint;
+
+use crate::{
+    instruction::Instruction,
+    memory::Memory,
+    opcode::{Opcode, Operand},
+};
+
+struct Row {
+    /// Index of the instruction the row starts at, `None` for the lines of a function body.
+    index: Option<usize>,
+    text: String,
+}
+
+/// One line per opcode showing where it is, its path, its spec name and its operands,
+/// followed by the body of any function it defines.
+fn rows(instructions: &[Instruction], names: &HashMap<BigUint, String>) -> Vec<String> {
+    let mut rows = Vec::new();
+    let mut jumps = Vec::new();
+    let digits = instructions.len().to_string().len();
+
+    let mut at = 0;
+    while at < instructions.len() {
+        let instruction = &instructions[at];
+        let Some(opcode) = Opcode::decode(instruction) else {
+            rows.push(Row {
+                index: Some(at),
+                text: format!("{at:>digits$}  {instruction:<12} unknown instruction"),
+            });
+            at += 1;
+            continue;
+        };
+
+        let mut text = format!("{at:>digits$}  {instruction:<12} {}", opcode.name());
+        for (operand, formatted) in opcode
+            .operands()
+            .iter()
+            .zip(opcode.format_operands(instructions, at))
+        {
+            text.push_str(&format!(" <{formatted}"));
+            match operand {
+                Operand::Identifier if opcode != Opcode::MakeOptional => {
+                    if let Some(name) = formatted.parse().ok().and_then(|id| names.get(&id)) {
+                        text.push_str(&format!(" {name}"));
+                    }
+                }
+                Operand::Target => jumps.push((rows.len(), formatted.parse::<usize>().ok())),
+                _ => {}
+            }
+            text.push('>');
+        }
+        rows.push(Row {
+            index: Some(at),
+            text,
+        });
+
+        let width = opcode.width(instructions, at);
+        if opcode == Opcode::DefineFunction {
+            let body = &instructions[(at + 4).min(instructions.len())
+                ..at.saturating_add(width).min(instructions.len())];
+            for line in self::rows(body, names) {
+                rows.push(Row {
+                    index: None,
+                    text: format!("{:digits$}      {line}", ""),
+                });
+            }
+        }
+        at = at.saturating_add(width);
+    }
+
+    if jumps
+        .iter()
+        .any(|(_, target)| *target == Some(instructions.len()))
+    {
+        rows.push(Row {
+            index: Some(instructions.len()),
+            text: format!("{:>digits$}  end", instructions.len()),
+        });
+    }
+
+    let mut arrows = Vec::new();
+    for (from, target) in jumps {
+        match rows
+            .iter()
+            .position(|row| row.index.is_some() && row.index == target)
+        {
+            Some(to) => arrows.push((from.min(to), from.max(to), to)),
+            None if target.is_some_and(|target| target > instructions.len()) => {
+                rows[from].text.push_str(" (past the end)")
+            }
+            None => rows[from]
+                .text
+                .push_str(" (not the start of an instruction)"),
+        }
+    }
+
+    draw_arrows(rows, arrows)
+}
+
+/// Draws each `(top, bottom, target)` arrow to the right of the rows, shorter arrows closer in.
+fn draw_arrows(rows: Vec<Row>, mut arrows: Vec<(usize, usize, usize)>) -> Vec<String> {
+    let width = rows
+        .iter()
+        .map(|row| row.text.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut lines: Vec<Vec<char>> = rows
+        .into_iter()
+        .map(|row| format!("{:width$} ", row.text).chars().collect())
+        .collect();
+
+    arrows.sort_by_key(|(top, bottom, _)| bottom - top);
+    let mut lanes: Vec<Vec<(usize, usize)>> = Vec::new();
+    let mut placed = Vec::new();
+    for (top, bottom, target) in arrows {
+        let lane = match lanes
+            .iter()
+            .position(|spans| spans.iter().all(|(t, b)| bottom < *t || top > *b))
+        {
+            Some(lane) => lane,
+            None => {
+                lanes.push(Vec::new());
+                lanes.len() - 1
+            }
+        };
+        lanes[lane].push((top, bottom));
+        placed.push((top, bottom, target, width + 4 + 2 * lane));
+    }
+
+    let set = |line: &mut Vec<char>, column: usize, c: char| {
+        if line.len() <= column {
+            line.resize(column + 1, ' ');
+        }
+        line[column] = c;
+    };
+    for &(top, bottom, _, column) in &placed {
+        // a jump to itself is a single row with nothing between its ends
+        for line in lines.iter_mut().take(bottom).skip(top + 1) {
+            set(line, column, '|');
+        }
+    }
+    for &(top, bottom, target, column) in &placed {
+        for row in [top, bottom] {
+            let line = &mut lines[row];
+            for c in width + 1..column {
+                if line.get(c).is_none_or(|c| *c == ' ') {
+                    set(line, c, '-');
+                }
+            }
+            set(line, column, '+');
+            if row == target {
+                set(line, width + 1, '<');
+            }
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|line| line.into_iter().collect::<String>().trim_end().to_owned())
+        .collect()
+}
+
+/// Lists `instructions` one opcode per line, with jump arrows on the right.
+pub fn disassemble(instructions: &[Instruction]) -> String {
+    let names = Memory::new().names();
+
+    let mut listing = String::new();
+    for line in rows(instructions, &names) {
+        listing.push_str(&line);
+        listing.push('\n');
+    }
+    listing
+}
@@ -2,23 +2,28 @@ use std::{
     cmp::Ordering,
     collections::{HashMap, VecDeque},
     fmt::Display,
-    io::stdin,
+    io::{self, stdin, stdout, BufRead, Read, Write},
+    sync::Arc,
 };
 
-use num_bigint::BigUint;
+use num_bigint::{BigInt, BigUint};
 use num_traits::Zero;
 
-use crate::{instruction::Instruction, Value};
+use crate::{
+    instruction::Instruction,
+    op::Code,
+    value::{ConversionError, Key, Value},
+};
 use anyhow::Result;
 use thiserror::Error;
 
-#[derive(Debug, Default, Clone, PartialEq)]
-pub(crate) enum BuiltInFunction {
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BuiltInFunction {
     #[default]
     None,
     Print,
     PrintLn,
-    // Read,
+    Read,
     ReadLn,
     ToBool,
     ToStr,
@@ -27,21 +32,62 @@ pub(crate) enum BuiltInFunction {
     Len,
     Push,
     Pop,
+    Get,
+    Set,
+    Remove,
+    Has,
+    Keys,
 }
 
 #[derive(Debug, Error)]
-pub(crate) enum FunctionCallError {
-    #[error("Invalid number of arguments: expected {expected}, got {got}")]
-    InvalidNumberOfArguments { expected: usize, got: usize },
+pub enum FunctionCallError {
+    #[error(
+        "{} takes {}{expected} argument(s), got {got}",
+        callee(*.function),
+        if *.varargs { "at least " } else { "" }
+    )]
+    InvalidNumberOfArguments {
+        /// [`BuiltInFunction::None`] for user-defined functions.
+        function: BuiltInFunction,
+        varargs: bool,
+        expected: usize,
+        got: usize,
+    },
+    #[error("{function} can't take {got} as argument, expected {expected}")]
+    InvalidArgumentType {
+        function: BuiltInFunction,
+        expected: &'static str,
+        got: &'static str,
+    },
+    #[error("{function} failed: {source}")]
+    Io {
+        function: BuiltInFunction,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// How errors name the function being called.
+fn callee(function: BuiltInFunction) -> String {
+    match function {
+        BuiltInFunction::None => "User-defined function".to_owned(),
+        function => function.to_string(),
+    }
+}
+
+impl Display for BuiltInFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
-pub(crate) struct Function {
+pub struct Function {
     varargs: bool,
     arity: usize,
 
     built_in: BuiltInFunction,
-    instructions: Vec<Instruction>,
+    code: Arc<Code>,
 }
 
 impl Display for Function {
@@ -61,12 +107,12 @@ impl Display for Function {
 }
 
 impl Function {
-    pub(crate) fn new(varargs: bool, arity: usize, instructions: Vec<Instruction>) -> Function {
+    pub fn new(varargs: bool, arity: usize, instructions: Vec<Instruction>) -> Function {
         Function {
             varargs,
             arity,
             built_in: BuiltInFunction::None,
-            instructions,
+            code: Arc::new(Code::decode(instructions)),
         }
     }
 
@@ -75,26 +121,66 @@ impl Function {
             varargs,
             arity,
             built_in,
-            instructions: vec![],
+            code: Arc::default(),
         }
     }
 
-    pub(crate) fn call(
-        &self,
-        args: VecDeque<Value>,
-        optionals: HashMap<BigUint, Value>,
-    ) -> Result<Option<Value>> {
-        if match args.len().cmp(&self.arity) {
+    pub fn varargs(&self) -> bool {
+        self.varargs
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn built_in(&self) -> BuiltInFunction {
+        self.built_in
+    }
+
+    pub fn is_built_in(&self) -> bool {
+        self.built_in != BuiltInFunction::None
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        self.code.instructions()
+    }
+
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    pub(crate) fn check_arguments(&self, got: usize) -> Result<()> {
+        if match got.cmp(&self.arity) {
             Ordering::Less => true,
             Ordering::Equal => false,
             Ordering::Greater => !self.varargs,
         } {
             return Err(FunctionCallError::InvalidNumberOfArguments {
+                function: self.built_in,
+                varargs: self.varargs,
                 expected: self.arity,
-                got: args.len(),
+                got,
             }
             .into());
         }
+        Ok(())
+    }
+
+    /// Calls a built-in function, user-defined ones are run by the interpreter.
+    pub(crate) fn call(
+        &self,
+        args: VecDeque<Value>,
+        optionals: HashMap<BigUint, Value>,
+    ) -> Result<Option<Value>> {
+        self.check_arguments(args.len())?;
+
+        let function = self.built_in;
+        let io = |source| FunctionCallError::Io { function, source };
+        let invalid_argument = |expected, got: &Value| FunctionCallError::InvalidArgumentType {
+            function,
+            expected,
+            got: got.type_name(),
+        };
 
         match self.built_in {
             BuiltInFunction::None => {}
@@ -106,25 +192,69 @@ impl Function {
                     }
                 }
 
+                let mut stdout = stdout().lock();
                 for (i, arg) in args.into_iter().enumerate() {
                     if i > 0 {
-                        print!("{sep}");
+                        write!(stdout, "{sep}").map_err(io)?;
                     }
-                    print!("{arg}");
+                    write!(stdout, "{arg}").map_err(io)?;
                 }
 
                 if let BuiltInFunction::PrintLn = self.built_in {
-                    print!("\n");
+                    writeln!(stdout).map_err(io)?;
+                }
+            }
+            BuiltInFunction::Read => {
+                let bytes = match optionals.get(&BigUint::zero()) {
+                    Some(bytes) => bytes.clone().try_into()?,
+                    None => false,
+                };
+                let mut stdin = stdin().lock();
+
+                let mut input = String::new();
+                match args.into_iter().next() {
+                    Some(count) => {
+                        let count = BigInt::try_from(count.clone())
+                            .ok()
+                            .and_then(|count| usize::try_from(count).ok())
+                            .ok_or_else(|| invalid_argument("non-negative integer", &count))?;
+                        for _ in 0..count {
+                            match if bytes {
+                                read_byte(&mut stdin).map_err(io)?.map(char::from)
+                            } else {
+                                read_char(&mut stdin).map_err(io)?
+                            } {
+                                Some(c) => input.push(c),
+                                None => break,
+                            }
+                        }
+                    }
+                    None => {
+                        let mut buffer = Vec::new();
+                        stdin.read_to_end(&mut buffer).map_err(io)?;
+                        if bytes {
+                            input.extend(buffer.into_iter().map(char::from));
+                        } else {
+                            input = String::from_utf8_lossy(&buffer).into_owned();
+                        }
+                    }
+                }
+
+                if input.is_empty() && stdin.fill_buf().map_err(io)?.is_empty() {
+                    return Ok(Some(Value::Null));
                 }
+                return Ok(Some(Value::String(input)));
             }
             BuiltInFunction::ReadLn => {
                 let mut input = String::new();
-                stdin().read_line(&mut input).unwrap();
+                if stdin().read_line(&mut input).map_err(io)? == 0 {
+                    return Ok(Some(Value::Null));
+                }
                 return Ok(Some(Value::String(input)));
             }
             BuiltInFunction::ToBool => {
                 return Ok(Some(Value::Boolean(
-                    args.into_iter().next().unwrap().into(),
+                    args.into_iter().next().unwrap().try_into()?,
                 )));
             }
             BuiltInFunction::ToStr => {
@@ -134,26 +264,25 @@ impl Function {
             }
             BuiltInFunction::ToInt => {
                 return Ok(Some(Value::Integer(
-                    args.into_iter().next().unwrap().into(),
+                    args.into_iter().next().unwrap().try_into()?,
                 )));
             }
             BuiltInFunction::Trim => {
                 return Ok(Some(Value::String(
                     match args.into_iter().next().unwrap() {
                         Value::String(s) => s.trim().to_owned(),
-                        _ => {
-                            panic!("Can only trim strings")
-                        }
+                        value => return Err(invalid_argument("string", &value).into()),
                     },
                 )));
             }
             BuiltInFunction::Len => {
                 return Ok(Some(Value::Integer(
                     match args.into_iter().next().unwrap() {
-                        Value::String(s) => s.len(),
+                        Value::String(s) => s.chars().count(),
                         Value::Array(a) => a.len(),
-                        _ => {
-                            panic!("Can only get length of strings and arrays")
+                        Value::Map(m) => m.len(),
+                        value => {
+                            return Err(invalid_argument("string, array or map", &value).into())
                         }
                     }
                     .into(),
@@ -161,47 +290,119 @@ impl Function {
             }
             BuiltInFunction::Push => {
                 let mut args = args.into_iter();
-                match args.next().unwrap().into() {
+                match args.next().unwrap() {
                     Value::Array(mut array) => {
-                        array.push(args.next().unwrap().into());
+                        array.push(args.next().unwrap());
                         return Ok(Some(Value::Array(array)));
                     }
                     Value::String(mut string) => {
-                        match args.next().unwrap().into() {
+                        match args.next().unwrap() {
                             Value::String(s) => string.push_str(&s),
-                            Value::Integer(i) => string.push(TryInto::<u8>::try_into(i)? as char),
-                            _ => {
-                                panic!("Can only push strings or characters to strings")
+                            Value::Integer(i) => string.push(
+                                u32::try_from(&i).ok().and_then(char::from_u32).ok_or_else(
+                                    || ConversionError::InvalidValue {
+                                        from: "integer",
+                                        value: i.to_string(),
+                                        to: "character",
+                                    },
+                                )?,
+                            ),
+                            value => {
+                                return Err(invalid_argument("string or integer", &value).into())
                             }
                         }
                         return Ok(Some(Value::String(string)));
                     }
-                    _ => {
-                        panic!("Can only push to arrays or strings")
-                    }
+                    value => return Err(invalid_argument("array or string", &value).into()),
                 }
             }
             BuiltInFunction::Pop => {
                 let mut args = args.into_iter();
-                match args.next().unwrap().into() {
+                match args.next().unwrap() {
                     Value::Array(mut array) => {
                         return Ok(Some(Value::Array(vec![
-                            array.pop().unwrap().into(),
+                            array.pop().unwrap_or(Value::Null),
                             Value::Array(array),
                         ])));
                     }
                     Value::String(mut string) => {
                         return Ok(Some(Value::Array(vec![
-                            Value::Integer((string.pop().unwrap() as u8).into()),
+                            string
+                                .pop()
+                                .map_or(Value::Null, |c| Value::Integer((c as u32).into())),
                             Value::String(string),
                         ])));
                     }
-                    _ => {
-                        panic!("Can only pop from arrays or strings")
-                    }
+                    value => return Err(invalid_argument("array or string", &value).into()),
+                }
+            }
+            BuiltInFunction::Get
+            | BuiltInFunction::Set
+            | BuiltInFunction::Remove
+            | BuiltInFunction::Has
+            | BuiltInFunction::Keys => {
+                let mut args = args.into_iter();
+                let mut map = match args.next().unwrap() {
+                    Value::Map(map) => map,
+                    value => return Err(invalid_argument("map", &value).into()),
+                };
+                if function == BuiltInFunction::Keys {
+                    return Ok(Some(Value::Array(
+                        map.into_keys().map(Value::from).collect(),
+                    )));
                 }
+
+                let key = Key::try_from(args.next().unwrap())?;
+                return Ok(Some(match function {
+                    BuiltInFunction::Get => map.remove(&key).unwrap_or(Value::Null),
+                    BuiltInFunction::Set => {
+                        map.insert(key, args.next().unwrap());
+                        Value::Map(map)
+                    }
+                    BuiltInFunction::Remove => {
+                        map.remove(&key);
+                        Value::Map(map)
+                    }
+                    _ => Value::Boolean(map.contains_key(&key)),
+                }));
             }
         }
         Ok(None)
     }
 }
+
+fn read_byte(reader: &mut impl BufRead) -> io::Result<Option<u8>> {
+    let byte = reader.fill_buf()?.first().copied();
+    if byte.is_some() {
+        reader.consume(1);
+    }
+    Ok(byte)
+}
+
+/// Reads one UTF-8 encoded character, invalid sequences become U+FFFD.
+fn read_char(reader: &mut impl BufRead) -> io::Result<Option<char>> {
+    let first = match read_byte(reader)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let width = match first.leading_ones() {
+        0 => 1,
+        2..=4 => first.leading_ones() as usize,
+        _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+    };
+
+    let mut buffer = [first, 0, 0, 0];
+    for byte in &mut buffer[1..width] {
+        match read_byte(reader)? {
+            Some(next) => *byte = next,
+            None => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+        }
+    }
+
+    Ok(Some(
+        std::str::from_utf8(&buffer[..width])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER),
+    ))
+}
@@ -1,4 +1,9 @@
-use std::{iter::Peekable, ops::Index, str::Chars};
+use std::{
+    fmt::{self, Display},
+    iter::Peekable,
+    ops::Index,
+    str::Chars,
+};
 
 use anyhow::Result;
 use num_bigint::BigUint;
@@ -6,7 +11,7 @@ use num_traits::{One, Zero};
 use thiserror::Error;
 
 #[derive(Default, Debug, Clone, PartialEq)]
-pub(crate) struct Instruction {
+pub struct Instruction {
     op: Vec<bool>,
 }
 
@@ -19,28 +24,65 @@ impl Instruction {
         }
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
+    pub fn is_empty(&self) -> bool {
         self.op.is_empty()
     }
 
-    pub(crate) fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         self.op.len()
     }
+
+    pub fn parts(&self) -> &[bool] {
+        &self.op
+    }
+
+    /// How many characters the instruction takes up in a path, its closing `/` included.
+    pub fn path_len(&self) -> usize {
+        self.op
+            .iter()
+            .map(|bit| if *bit { 3 } else { 2 })
+            .sum::<usize>()
+            + 1
+    }
+
+    /// Writes the digits after the binary point of `fraction`, the inverse of [`Instruction::fraction`].
+    pub fn from_fraction(mut fraction: f64) -> Instruction {
+        let mut op = Vec::new();
+        while fraction > 0.0 {
+            fraction *= 2.0;
+            op.push(fraction >= 1.0);
+            fraction = fraction.fract();
+        }
+        Instruction { op }
+    }
+
+    /// Reads the parts as the digits after a binary point, the first part being worth 1/2.
+    pub fn fraction(&self) -> f64 {
+        let mut result = 0.0;
+        let mut digit = 0.5;
+        for bit in &self.op {
+            if *bit {
+                result += digit;
+            }
+            digit /= 2.0;
+        }
+        result
+    }
 }
 
 #[derive(Debug, Error)]
-pub(crate) enum ParseInstructionError {
+pub enum ParseInstructionError {
     #[error("Instruction not finished")]
     UnfinishedInstruction,
 }
 
-pub(crate) enum ParsedInstruction {
+pub enum ParsedInstruction {
     Instruction(Instruction),
     Err(ParseInstructionError),
     CodePartStopped,
 }
 
-pub(crate) trait ParseInstruction {
+pub trait ParseInstruction {
     fn parse_instruction(&mut self) -> ParsedInstruction;
 }
 
@@ -97,11 +139,15 @@ impl From<&Instruction> for BigUint {
     }
 }
 
+/// Saturates at `usize::MAX` for numbers that don't fit.
 impl From<&Instruction> for usize {
     fn from(instruction: &Instruction) -> Self {
         let mut result = 0;
         for (i, bit) in instruction.op.iter().enumerate() {
             if *bit {
+                if i >= usize::BITS as usize {
+                    return usize::MAX;
+                }
                 result += 1 << i;
             }
         }
@@ -119,17 +165,74 @@ impl From<&Instruction> for bool {
     }
 }
 
-impl From<&Instruction> for Result<u8> {
+/// Reads a character as its code point, most significant part first.
+impl From<&Instruction> for Result<char> {
     fn from(instruction: &Instruction) -> Self {
-        if instruction.len() != 8 {
-            return Err(anyhow::anyhow!("Invalid instruction length"));
+        let mut result: u32 = 0;
+        for bit in &instruction.op {
+            result = result
+                .checked_mul(2)
+                .ok_or_else(|| anyhow::anyhow!("Not a character"))?
+                + *bit as u32;
         }
-        let mut result = 0;
-        for (i, bit) in instruction.op.iter().rev().enumerate() {
-            if *bit {
-                result += 1 << i;
-            }
+        char::from_u32(result).ok_or_else(|| anyhow::anyhow!("Not a character"))
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut path = String::new();
+        for bit in &self.op {
+            path.push_str(if *bit { "../" } else { "./" });
+        }
+        path.push('/');
+        f.pad(&path)
+    }
+}
+
+impl From<Vec<bool>> for Instruction {
+    fn from(op: Vec<bool>) -> Self {
+        Instruction { op }
+    }
+}
+
+impl From<&BigUint> for Instruction {
+    fn from(value: &BigUint) -> Self {
+        Instruction {
+            op: (0..value.bits()).map(|i| value.bit(i)).collect(),
+        }
+    }
+}
+
+impl From<usize> for Instruction {
+    fn from(value: usize) -> Self {
+        (&BigUint::from(value)).into()
+    }
+}
+
+impl From<bool> for Instruction {
+    fn from(value: bool) -> Self {
+        Instruction {
+            op: if value { vec![true] } else { vec![] },
+        }
+    }
+}
+
+impl From<u8> for Instruction {
+    fn from(value: u8) -> Self {
+        Instruction {
+            op: (0..8).rev().map(|i| value & (1 << i) != 0).collect(),
+        }
+    }
+}
+
+/// Writes the code point most significant part first, in 8 parts when it fits in them.
+impl From<char> for Instruction {
+    fn from(value: char) -> Self {
+        let value = value as u32;
+        let parts = (u32::BITS - value.leading_zeros()).max(8);
+        Instruction {
+            op: (0..parts).rev().map(|i| value & (1 << i) != 0).collect(),
         }
-        Ok(result)
     }
 }
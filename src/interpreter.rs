@@ -0,0 +1,591 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::{self, Display},
+};
+
+use anyhow::Result;
+use num_bigint::{BigInt, BigUint};
+use thiserror::Error;
+
+use crate::{
+    arithmetic::{self, BinaryOperator},
+    function::Function,
+    memory::{self, Memory},
+    op::{Code, Op},
+    opcode::Opcode,
+    program::Program,
+    stack_value::StackValue,
+    value::{Key, Value},
+};
+
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("Stack underflow")]
+    StackUnderflow,
+    #[error("Invalid instruction")]
+    InvalidInstruction,
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Negative exponent {0} on an integer")]
+    NegativeExponent(BigInt),
+    #[error("Negative shift by {0}")]
+    NegativeShift(BigInt),
+    #[error("More than {MAX_CALL_DEPTH} nested calls")]
+    CallDepthExceeded,
+    #[error("Repeating gives more than {MAX_REPEATED_LENGTH} bytes or elements")]
+    TooLong,
+}
+
+/// How long repeating a string or array can make it, counted in bytes for strings.
+pub const MAX_REPEATED_LENGTH: usize = 1 << 28;
+
+/// How many user-defined calls can run inside each other, every one of them takes up some of
+/// the native stack.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// How many values from the top of the stack an [`ExecutionError`] keeps.
+const STACK_SNAPSHOT: usize = 5;
+
+/// An error raised while running an instruction, along with where it happened.
+#[derive(Debug, Error)]
+pub struct ExecutionError {
+    /// Index of the failing instruction, counted like jumps do.
+    pub index: usize,
+    /// `None` when the instruction isn't a known opcode.
+    pub opcode: Option<Opcode>,
+    pub operands: Vec<String>,
+    /// The top of the stack when the error happened, top last.
+    pub stack: Vec<StackValue>,
+    /// Indices of the calls the error happened in, innermost first.
+    pub callers: Vec<usize>,
+    #[source]
+    pub error: anyhow::Error,
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.error)?;
+        write!(f, "  at instruction {}: ", self.index)?;
+        match self.opcode {
+            Some(opcode) => write!(f, "{}", opcode.name())?,
+            None => write!(f, "unknown instruction")?,
+        }
+        for operand in &self.operands {
+            write!(f, " <{operand}>")?;
+        }
+        // deep recursion calls from the same place over and over
+        let mut callers = self.callers.iter().peekable();
+        while let Some(caller) = callers.next() {
+            let mut times = 1;
+            while callers.next_if_eq(&caller).is_some() {
+                times += 1;
+            }
+            write!(f, "\n  called from instruction {caller}")?;
+            if times > 1 {
+                write!(f, " ({times} times)")?;
+            }
+        }
+        write!(f, "\n  stack (top last):")?;
+        if self.stack.is_empty() {
+            write!(f, " empty")?;
+        }
+        for value in &self.stack {
+            write!(f, "\n    {}", value.describe())?;
+        }
+        Ok(())
+    }
+}
+
+enum Flow {
+    Continue(usize),
+    Return(Value),
+}
+
+/// Gets to look at every op the interpreter runs, function bodies included.
+///
+/// `ptr` is the index of the op in `code`, which is the code of the function being run
+/// when [`Memory::depth`] isn't 0. An error stops the program at that op.
+pub trait Hook {
+    /// Called before the op at `ptr` runs.
+    fn before(&mut self, _interpreter: &Interpreter, _code: &Code, _ptr: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after the op at `ptr` ran without an error.
+    fn after(&mut self, _interpreter: &Interpreter, _code: &Code, _ptr: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Executes programs, keeping its stack and memory between runs.
+pub struct Interpreter {
+    stack: Vec<StackValue>,
+    memory: Memory,
+    hook: Option<Box<dyn Hook>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            stack: Vec::new(),
+            memory: Memory::new(),
+            hook: None,
+        }
+    }
+
+    /// Calls `hook` around every op run from now on.
+    pub fn set_hook(&mut self, hook: impl Hook + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    pub fn stack(&self) -> &[StackValue] {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Makes `args` the arguments the program sees in [`memory::ARGS`].
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.memory.set_global(
+            memory::ARGS.into(),
+            Value::Array(args.into_iter().map(Value::String).collect()),
+        );
+    }
+
+    pub fn clear_stack(&mut self) {
+        self.stack.clear();
+    }
+
+    /// Runs `program` to completion.
+    ///
+    /// Returning at the top level stops the program and leaves the returned value on the stack.
+    pub fn run(&mut self, program: &Program) -> Result<()> {
+        if let Some(value) = self.execute(program.code())? {
+            self.stack.push(value.into());
+        }
+        Ok(())
+    }
+
+    /// Calls a user-defined function, its arguments are pushed in order with any extra
+    /// varargs collected into an array on top and its optionals become local variables.
+    /// The stack from `base` up, where the call and its arguments were, is dropped once the
+    /// call can go ahead.
+    fn call(
+        &mut self,
+        function: &Function,
+        base: usize,
+        args: VecDeque<Value>,
+        optionals: HashMap<BigUint, Value>,
+    ) -> Result<Option<Value>> {
+        function.check_arguments(args.len())?;
+        if self.memory.depth() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::CallDepthExceeded.into());
+        }
+
+        self.stack.truncate(base);
+        let mut args = args.into_iter();
+        for arg in args.by_ref().take(function.arity()) {
+            self.stack.push(arg.into());
+        }
+        if function.varargs() {
+            self.stack.push(Value::Array(args.collect()).into());
+        }
+
+        self.memory.push_frame(optionals);
+        let result = self.execute(function.code());
+        self.memory.pop_frame();
+        self.stack.truncate(base);
+        result
+    }
+
+    /// Runs `code` until it ends or returns.
+    fn execute(&mut self, code: &Code) -> Result<Option<Value>> {
+        let mut ptr = 0;
+        while let Some(op) = code.ops().get(ptr) {
+            let flow = self
+                .hook(|hook, interpreter| hook.before(interpreter, code, ptr))
+                .and_then(|()| self.step(op, ptr))
+                .and_then(|flow| {
+                    self.hook(|hook, interpreter| hook.after(interpreter, code, ptr))?;
+                    Ok(flow)
+                });
+            match flow {
+                Ok(Flow::Continue(next)) => ptr = next,
+                Ok(Flow::Return(value)) => return Ok(Some(value)),
+                Err(error) => return Err(self.locate(error, code, ptr)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Calls `f` on the hook if there is one, taking it out meanwhile so it can see `self`.
+    fn hook(&mut self, f: impl FnOnce(&mut dyn Hook, &Interpreter) -> Result<()>) -> Result<()> {
+        let Some(mut hook) = self.hook.take() else {
+            return Ok(());
+        };
+        let result = f(hook.as_mut(), self);
+        self.hook = Some(hook);
+        result
+    }
+
+    /// Attaches where `error` happened, or which call it happened in if it already has a location.
+    fn locate(&self, mut error: anyhow::Error, code: &Code, ptr: usize) -> anyhow::Error {
+        let index = code.position(ptr);
+        if let Some(located) = error.downcast_mut::<ExecutionError>() {
+            located.callers.push(index);
+            return error;
+        }
+
+        let instructions = code.instructions();
+        let opcode = Opcode::decode(&instructions[index]);
+        ExecutionError {
+            index,
+            opcode,
+            operands: opcode.map_or_else(Vec::new, |opcode| {
+                opcode.format_operands(instructions, index)
+            }),
+            stack: self
+                .stack
+                .iter()
+                .rev()
+                .take(STACK_SNAPSHOT)
+                .rev()
+                .cloned()
+                .collect(),
+            callers: Vec::new(),
+            error,
+        }
+        .into()
+    }
+
+    /// The top `N` values, top last, left on the stack so an error still shows them.
+    fn peek<const N: usize>(&self) -> Result<&[StackValue; N]> {
+        let start = self.start(N)?;
+        Ok(self.stack[start..].try_into().expect("slice has N values"))
+    }
+
+    /// Index of the lowest of the top `n` values.
+    fn start(&self, n: usize) -> Result<usize> {
+        Ok(self
+            .stack
+            .len()
+            .checked_sub(n)
+            .ok_or(RuntimeError::StackUnderflow)?)
+    }
+
+    /// The top value if it can be stored, optionals can only be passed to calls.
+    fn peek_value(&self) -> Result<&Value> {
+        match self.stack.last().ok_or(RuntimeError::StackUnderflow)? {
+            StackValue::Value(value) | StackValue::Argument(value) => Ok(value),
+            StackValue::Optional(_, _) => Err(RuntimeError::InvalidInstruction.into()),
+        }
+    }
+
+    /// Replaces the top `n` values with the `value` an op worked out from them.
+    fn replace(&mut self, n: usize, value: Value) {
+        self.stack.truncate(self.stack.len() - n);
+        self.stack.push(value.into());
+    }
+
+    fn pop(&mut self) -> Result<StackValue> {
+        Ok(self.stack.pop().ok_or(RuntimeError::StackUnderflow)?)
+    }
+
+    /// Executes `op`, the op at `ptr`, giving back where to continue.
+    fn step(&mut self, op: &Op, ptr: usize) -> Result<Flow> {
+        match op {
+            Op::Noop => {}
+            Op::Duplicate => {
+                let value = self
+                    .stack
+                    .last()
+                    .ok_or(RuntimeError::StackUnderflow)?
+                    .clone();
+                self.stack.push(value);
+            }
+            Op::Pop => {
+                self.pop()?;
+            }
+            Op::PushInteger(int) => self.stack.push(Value::Integer(int.clone()).into()),
+            Op::PopToVariable(index) => {
+                let value = self.peek_value()?.clone();
+                self.stack.pop();
+                self.memory.set(index.clone(), value);
+            }
+            Op::PushVariable(index) => self.stack.push(StackValue::Value(
+                self.memory
+                    .get(index.clone())
+                    .ok_or(RuntimeError::InvalidInstruction)?
+                    .clone(),
+            )),
+            Op::PushString(string) => self.stack.push(Value::String(string.clone()).into()),
+            Op::PushFloat(float) => self.stack.push(Value::Float(*float).into()),
+            Op::Call => {
+                // everything above the topmost function goes to it, and stays on the stack
+                // until the call can't fail on it anymore
+                let base = self
+                    .stack
+                    .iter()
+                    .rposition(|value| matches!(value, StackValue::Value(Value::Function(_))))
+                    .ok_or(RuntimeError::StackUnderflow)?;
+                let StackValue::Value(Value::Function(function)) = self.stack[base].clone() else {
+                    unreachable!("rposition found a function");
+                };
+                let mut args = VecDeque::new();
+                let mut optionals = HashMap::new();
+                for value in self.stack[base + 1..].iter().rev() {
+                    match value.clone() {
+                        StackValue::Value(value) | StackValue::Argument(value) => {
+                            args.push_front(value)
+                        }
+                        StackValue::Optional(index, value) => {
+                            optionals.insert(index, value);
+                        }
+                    }
+                }
+
+                let result = if function.is_built_in() {
+                    let result = function.call(args, optionals)?;
+                    self.stack.truncate(base);
+                    result
+                } else {
+                    self.call(&function, base, args, optionals)?
+                };
+                if let Some(value) = result {
+                    self.stack.push(StackValue::Value(value));
+                }
+            }
+            Op::MakeArgument => {
+                let value = match self.peek()? {
+                    [StackValue::Value(value)] => value.clone(),
+                    [StackValue::Argument(_) | StackValue::Optional(_, _)] => {
+                        return Err(RuntimeError::InvalidInstruction.into())
+                    }
+                };
+                self.stack.pop();
+                self.stack.push(StackValue::Argument(value));
+            }
+            Op::MakeOptional(index) => {
+                let value = self.peek_value()?.clone();
+                self.stack.pop();
+                self.stack.push(StackValue::Optional(index.clone(), value));
+            }
+            Op::Jump(target) => return Ok(Flow::Continue(*target)),
+            Op::Equal | Op::NotEqual => {
+                let [v2, v1] = self.peek()?;
+                let equal = v1.equals(v2);
+                self.replace(2, Value::Boolean(equal == (*op == Op::Equal)));
+            }
+            Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual => {
+                let [v2, v1] = self.peek()?;
+                let ordering = v1.partial_cmp(v2).ok_or(RuntimeError::InvalidInstruction)?;
+                let holds = arithmetic::compare(op, ordering).unwrap_or_default();
+                self.replace(2, Value::Boolean(holds));
+            }
+            Op::Index => {
+                let [value, index] = self.peek()?;
+
+                // anything out of range gives null, the container stays below the element
+                let element = match (value, index) {
+                    (
+                        StackValue::Value(Value::Array(array)),
+                        StackValue::Value(Value::Integer(index)),
+                    ) => usize::try_from(index)
+                        .ok()
+                        .and_then(|index| array.get(index))
+                        .cloned()
+                        .unwrap_or(Value::Null),
+                    (
+                        StackValue::Value(Value::String(string)),
+                        StackValue::Value(Value::Integer(index)),
+                    ) => match usize::try_from(index)
+                        .ok()
+                        .and_then(|index| string.chars().nth(index))
+                    {
+                        Some(c) => Value::Integer((c as u32).into()),
+                        None => Value::Null,
+                    },
+                    (StackValue::Value(Value::Map(map)), StackValue::Value(key)) => map
+                        .get(&Key::try_from(key.clone())?)
+                        .cloned()
+                        .unwrap_or(Value::Null),
+                    _ => return Err(RuntimeError::InvalidInstruction.into()),
+                };
+                self.replace(1, element);
+            }
+            Op::RemoveVariable(index) => {
+                self.memory.remove(index.clone());
+            }
+            Op::JumpIfFalse(target) | Op::JumpIfTrue(target) => {
+                let condition = bool::try_from(self.peek_value()?.clone())?;
+                self.stack.pop();
+                if matches!(op, Op::JumpIfTrue(_)) == condition {
+                    return Ok(Flow::Continue(*target));
+                }
+            }
+            Op::PushFalse => self.stack.push(false.into()),
+            Op::PushTrue => self.stack.push(true.into()),
+            Op::PushNull => self.stack.push(Value::Null.into()),
+            Op::IsNull => {
+                let is_null = *self.peek_value()? == Value::Null;
+                self.replace(1, Value::Boolean(is_null));
+            }
+            Op::PushArray(length) => {
+                let start = self.start(*length)?;
+                let array = self.stack.drain(start..).rev().map(Value::from).collect();
+                self.stack.push(Value::Array(array).into());
+            }
+            Op::PushMap(length) => {
+                let start = self.start(length.saturating_mul(2))?;
+                // pairs pushed later are closer to the top and win over earlier ones
+                let mut map = BTreeMap::new();
+                for pair in self.stack[start..].rchunks_exact(2) {
+                    let key = Key::try_from(Value::from(pair[0].clone()))?;
+                    map.entry(key).or_insert_with(|| pair[1].clone().into());
+                }
+                self.replace(self.stack.len() - start, Value::Map(map));
+            }
+            Op::SpreadArray => {
+                let array = match self.peek()? {
+                    [StackValue::Value(Value::Array(array))] => array.clone(),
+                    _ => return Err(RuntimeError::InvalidInstruction.into()),
+                };
+                self.stack.pop();
+                for value in array.into_iter().rev() {
+                    self.stack.push(StackValue::Value(value));
+                }
+            }
+            Op::Negate => {
+                let [value] = self.peek()?;
+                let value = arithmetic::negate(value.clone().into())?;
+                self.replace(1, value);
+            }
+            Op::Not => {
+                let [value] = self.peek()?;
+                let value = arithmetic::not(value.clone().into())?;
+                self.replace(1, value);
+            }
+            Op::Add
+            | Op::Subtract
+            | Op::Multiply
+            | Op::Divide
+            | Op::Remainder
+            | Op::Power
+            | Op::FloorDivide
+            | Op::BitAnd
+            | Op::BitOr
+            | Op::BitXor
+            | Op::ShiftLeft
+            | Op::ShiftRight
+            | Op::And
+            | Op::Or => {
+                let operator = BinaryOperator::of(op).ok_or(RuntimeError::InvalidInstruction)?;
+                let [v2, v1] = self.peek()?;
+                let value = arithmetic::binary(operator, v1.clone().into(), v2.clone().into())?;
+                self.replace(2, value);
+            }
+            Op::Swap(i1, i2) => {
+                // 0 is top
+                let i1 = self
+                    .stack
+                    .len()
+                    .checked_sub(*i1)
+                    .and_then(|x| x.checked_sub(1))
+                    .ok_or(RuntimeError::InvalidInstruction)?;
+                let i2 = self
+                    .stack
+                    .len()
+                    .checked_sub(*i2)
+                    .and_then(|x| x.checked_sub(1))
+                    .ok_or(RuntimeError::InvalidInstruction)?;
+                self.stack.swap(i1, i2);
+            }
+            Op::DefineFunction(function) => {
+                self.stack.push(Value::Function(function.clone()).into())
+            }
+            Op::Return => return Ok(Flow::Return(self.pop()?.into())),
+            Op::PushGlobal(index) => self.stack.push(StackValue::Value(
+                self.memory
+                    .get_global(index.clone())
+                    .ok_or(RuntimeError::InvalidInstruction)?
+                    .clone(),
+            )),
+            Op::PopToGlobal(index) => {
+                let value = self.peek_value()?.clone();
+                self.stack.pop();
+                self.memory.set_global(index.clone(), value);
+            }
+            Op::Invalid => return Err(RuntimeError::InvalidInstruction.into()),
+        }
+
+        Ok(Flow::Continue(ptr + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    /// The stack snapshot of the error running `source` fails with.
+    fn failing_stack(source: &str) -> Vec<String> {
+        let error = Interpreter::new()
+            .run(&assemble(source).unwrap())
+            .unwrap_err();
+        let error = error.downcast_ref::<ExecutionError>().unwrap();
+        error.stack.iter().map(StackValue::describe).collect()
+    }
+
+    /// The stack after running `source`, top last.
+    fn stack(source: &str) -> Vec<String> {
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&assemble(source).unwrap()).unwrap();
+        interpreter
+            .stack()
+            .iter()
+            .map(StackValue::describe)
+            .collect()
+    }
+
+    #[test]
+    fn null_is_only_equal_to_null() {
+        assert_eq!(
+            stack("push 1\npush null\neq\npush null\npush 1\nne\npush null\npush null\neq"),
+            ["false", "true", "true"]
+        );
+        assert_eq!(
+            stack("push \"\"\npush null\neq\narray 0\npush null\nne\npush false\npush null\neq"),
+            ["false", "true", "false"]
+        );
+    }
+
+    #[test]
+    fn keeps_call_operands_on_errors() {
+        assert_eq!(
+            failing_stack("push 7\nload Trim\npush 3\ncall"),
+            ["7", "<function constant arity=1 built-in>", "3"]
+        );
+    }
+
+    #[test]
+    fn keeps_conditions_on_errors() {
+        assert_eq!(
+            failing_stack("func 0\nend\njumpif 0"),
+            ["<function constant arity=0 user-defined>"]
+        );
+    }
+
+    #[test]
+    fn keeps_indexed_values_on_errors() {
+        assert_eq!(failing_stack("push 1\npush 2\nindex"), ["1", "2"]);
+    }
+}
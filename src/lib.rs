@@ -0,0 +1,32 @@
+//! Interpreter for ./..//, the language where every program is a path.
+//!
+//! ```
+//! use path_lang::{Interpreter, Program};
+//!
+//! let program = Program::parse("/././/..//..//").unwrap();
+//! let mut interpreter = Interpreter::new();
+//! interpreter.run(&program).unwrap();
+//! assert_eq!(interpreter.stack().len(), 1);
+//! ```
+
+pub mod analysis;
+pub mod arithmetic;
+pub mod asm;
+pub mod check;
+pub mod debugger;
+pub mod disasm;
+pub mod function;
+pub mod instruction;
+pub mod interpreter;
+pub mod memory;
+pub mod op;
+pub mod opcode;
+pub mod optimize;
+pub mod program;
+pub mod repl;
+pub mod stack_value;
+pub mod trace;
+pub mod value;
+
+pub use interpreter::{ExecutionError, Hook, Interpreter, RuntimeError};
+pub use program::{parse, Program, SyntaxError};
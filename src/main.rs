@@ -1,542 +1,202 @@
-mod function;
-mod instruction;
-mod memory;
-mod stack_value;
-mod value;
-
 use std::{
-    cmp::Ordering,
-    collections::{HashMap, VecDeque},
-    env,
+    env, fs,
+    io::{self, Read},
+    path::Path,
+    process::{Command, ExitCode},
 };
 
 use anyhow::Result;
-
-use instruction::{Instruction, ParseInstruction, ParsedInstruction};
-use memory::Memory;
-use num_bigint::{BigInt, BigUint};
-use num_traits::{One, Pow, Zero};
-use stack_value::StackValue;
 use thiserror::Error;
-use value::Value;
 
-#[derive(Debug, Error)]
-enum SyntaxError {
-    #[error("Doesn't start at /")]
-    NotNoop,
-}
+use path_lang::{
+    analysis, asm, check, debugger::Debugger, disasm, repl, trace, Interpreter, Program,
+};
+
+const USAGE: &str = "\
+Usage: path_lang MODE [ARGUMENTS]
+   or: /PATH, running the path the binary is invoked as
+
+Modes, reading stdin when FILE is left out or is -:
+  run [FILE [ARGS]]   run a path, the program sees ARGS in Args
+  -e PATH [ARGS]      run a path given as the argument
+  /PATH [ARGS]        the same, for systems where programs can't be named by a path
+  exec FILE [ARGS]    run the binary again as the path in FILE, like /PATH would be run
+  asm [FILE]          turn mnemonics into a path
+  disasm [FILE]       list the instructions of a path
+  check [FILE]        report problems in a path without running it
+  optimize [FILE]     shorten a path
+  repl                run lines of instructions one at a time
+  debug FILE          run a path in the step debugger
+  trace LOG [FILE]    run a path, tracing every instruction to LOG
+  help                show this";
 
 #[derive(Debug, Error)]
-enum RuntimeError {
-    #[error("Stack underflow")]
-    StackUnderflow,
-    #[error("Invalid instruction")]
-    InvalidInstruction,
+enum UsageError {
+    #[error("Unknown mode {0:?}, see path_lang help")]
+    UnknownMode(String),
+    #[error("Expected a mode, see path_lang help")]
+    MissingMode,
+    #[error("-e needs a path")]
+    MissingPath,
+    #[error("exec needs a file")]
+    MissingExec,
+    #[error("Found {0} problem(s)")]
+    Problems(usize),
+    #[error("{0} needs a file, stdin is taken by its commands")]
+    MissingFile(String),
+    #[error("trace needs a file to write the trace to")]
+    MissingLog,
+    #[error("{0} has no instructions, use path_lang run {0} to run a file")]
+    EmptyPath(String),
 }
 
-fn parse(path: String) -> Result<Vec<Instruction>> {
-    let mut chars = path.chars().peekable();
-    if chars.next().expect("How did you even run this?") != '/' {
-        return Err(SyntaxError::NotNoop.into());
-    }
-
-    let mut instructions = Vec::new();
-
-    loop {
-        match chars.parse_instruction() {
-            ParsedInstruction::Instruction(instruction) => {
-                instructions.push(instruction);
-            }
-            ParsedInstruction::Err(e) => return Err(e.into()),
-            ParsedInstruction::CodePartStopped => break,
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
         }
     }
-
-    Ok(instructions)
 }
 
-fn run(instructions: Vec<Instruction>) -> Result<()> {
-    let mut stack: Vec<StackValue> = Vec::new();
-    let mut memory = Memory::new();
+/// Reads `file`, or stdin when there is none or it is `-`.
+fn read_source(file: Option<String>) -> Result<String> {
+    Ok(match file.filter(|file| file != "-") {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            source
+        }
+    })
+}
 
-    let mut ptr = 0;
-    while ptr < instructions.len() {
-        let mut jumped = false;
-        let instruction = &instructions[ptr];
+/// Runs `path`, which sees `args` as its arguments.
+fn run_path(path: &str, args: impl Iterator<Item = String>) -> Result<()> {
+    let program = Program::parse(path)?;
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(args.collect());
+    interpreter.run(&program)
+}
 
-        match instruction.len() {
-            0 => {}
-            1 => {
-                if !instruction[0] {
-                    // duplicate top of stack
-                    let value = stack.last().ok_or(RuntimeError::StackUnderflow)?.clone();
-                    stack.push(value);
-                } else {
-                    // pop top of stack
-                    stack.pop().ok_or(RuntimeError::StackUnderflow)?;
-                }
-            }
-            2 => {
-                if !instruction[0] {
-                    if !instruction[1] {
-                        // push integer
-                        let int: BigUint = match instructions.get(ptr + 2) {
-                            Some(instruction) => instruction,
-                            None => return Err(RuntimeError::InvalidInstruction.into()),
-                        }
-                        .into();
-                        if match instructions.get(ptr + 1) {
-                            Some(instruction) => instruction,
-                            None => return Err(RuntimeError::InvalidInstruction.into()),
-                        }
-                        .into()
-                        {
-                            stack.push(int.into());
-                        } else {
-                            stack.push((-BigInt::from(int)).into());
-                        }
+/// Replaces the process with the binary run as `path`, which is how the shell would run it.
+#[cfg(unix)]
+fn exec(path: &str, args: impl Iterator<Item = String>) -> Result<()> {
+    use std::os::unix::process::CommandExt;
 
-                        ptr += 2;
-                    } else {
-                        // pop to variable
-                        ptr += 1;
-                        let index: BigUint = match instructions.get(ptr) {
-                            Some(instruction) => instruction,
-                            None => return Err(RuntimeError::InvalidInstruction.into()),
-                        }
-                        .into();
+    Err(Command::new(env::current_exe()?)
+        .arg0(path)
+        .args(args)
+        .exec()
+        .into())
+}
 
-                        memory.set(
-                            index,
-                            match stack.pop().ok_or(RuntimeError::StackUnderflow)? {
-                                StackValue::Value(value) | StackValue::Argument(value) => value,
-                                StackValue::Optional(_, _) => {
-                                    return Err(RuntimeError::InvalidInstruction.into())
-                                }
-                            },
-                        );
-                    }
-                } else {
-                    if !instruction[1] {
-                        // push variable
-                        ptr += 1;
-                        let index: BigUint = match instructions.get(ptr) {
-                            Some(instruction) => instruction,
-                            None => return Err(RuntimeError::InvalidInstruction.into()),
-                        }
-                        .into();
+/// Runs the binary as `path` and exits with its status, since the process can't be replaced.
+#[cfg(not(unix))]
+fn exec(path: &str, args: impl Iterator<Item = String>) -> Result<()> {
+    let status = Command::new(env::current_exe()?)
+        .arg(path)
+        .args(args)
+        .status()?;
+    std::process::exit(status.code().unwrap_or(1))
+}
 
-                        stack.push(StackValue::Value(
-                            memory
-                                .get(index)
-                                .ok_or(RuntimeError::InvalidInstruction)?
-                                .clone(),
-                        ));
-                    } else {
-                        // push string
-                        ptr += 1;
-                        let length = match instructions.get(ptr) {
-                            Some(instruction) => instruction,
-                            None => return Err(RuntimeError::InvalidInstruction.into()),
-                        }
-                        .into();
+/// Whether the binary was invoked as a path program rather than as itself, which an absolute
+/// path to the binary also starts with `/`.
+fn invoked_as_path(name: &str) -> bool {
+    name.starts_with('/') && env::current_exe().map_or(true, |exe| exe != Path::new(name))
+}
 
-                        let mut string = String::new();
-                        for _ in 0..length {
-                            ptr += 1;
-                            string.push(
-                                Into::<Result<u8>>::into(match instructions.get(ptr) {
-                                    Some(instruction) => instruction,
-                                    None => return Err(RuntimeError::InvalidInstruction.into()),
-                                })?
-                                .into(),
-                            );
-                        }
+fn run() -> Result<()> {
+    let mut args = env::args();
+    let name = args.next().expect("How did you even run this?");
+    if invoked_as_path(&name) {
+        return run_path(&name, args);
+    }
 
-                        stack.push(StackValue::Value(Value::String(string)));
-                    }
-                }
+    match args.next() {
+        Some(path) if path.starts_with('/') => {
+            // a file name is almost always an empty program, running it was meant
+            if Program::parse(&path).is_ok_and(|program| program.instructions().is_empty()) {
+                return Err(UsageError::EmptyPath(path).into());
             }
-            3 => {
-                if !instruction[0] {
-                    if !instruction[1] {
-                        if !instruction[2] {
-                            // push float
-                            todo!("Floats");
-                        } else {
-                            // call function
-                            let mut args = VecDeque::new();
-                            let mut optionals = HashMap::new();
-                            loop {
-                                match stack.pop() {
-                                    Some(StackValue::Value(Value::Function(function))) => {
-                                        if let Some(value) = function.call(args, optionals)? {
-                                            stack.push(StackValue::Value(value));
-                                        }
-                                        break;
-                                    }
-                                    Some(StackValue::Value(value))
-                                    | Some(StackValue::Argument(value)) => args.push_front(value),
-                                    Some(StackValue::Optional(index, value)) => {
-                                        optionals.insert(index, value);
-                                    }
-                                    None => return Err(RuntimeError::StackUnderflow.into()),
-                                }
-                            }
-                        }
-                    } else {
-                        if !instruction[2] {
-                            // make argument
-                            let value = stack.pop().ok_or(RuntimeError::StackUnderflow)?;
-                            stack.push(StackValue::Argument(match value {
-                                StackValue::Value(value) => value,
-                                StackValue::Argument(_) | StackValue::Optional(_, _) => {
-                                    return Err(RuntimeError::InvalidInstruction.into())
-                                }
-                            }));
-                        } else {
-                            // make optional argument
-                            let value = stack.pop().ok_or(RuntimeError::StackUnderflow)?;
-                            ptr += 1;
-                            let index: BigUint = match instructions.get(ptr) {
-                                Some(instruction) => instruction,
-                                None => return Err(RuntimeError::InvalidInstruction.into()),
-                            }
-                            .into();
-
-                            stack.push(StackValue::Optional(
-                                index,
-                                match value {
-                                    StackValue::Value(value) | StackValue::Argument(value) => value,
-                                    StackValue::Optional(_, _) => {
-                                        return Err(RuntimeError::InvalidInstruction.into())
-                                    }
-                                },
-                            ));
-                        }
-                    }
-                } else {
-                    if !instruction[1] && !instruction[2] {
-                        // jump to instruction
-                        ptr = match instructions.get(ptr + 1) {
-                            Some(instruction) => instruction,
-                            None => return Err(RuntimeError::InvalidInstruction.into()),
-                        }
-                        .into();
-                        jumped = true;
-                    } else {
-                        match (
-                            instruction[1],
-                            instruction[2],
-                            stack
-                                .pop()
-                                .ok_or(RuntimeError::StackUnderflow)?
-                                .partial_cmp(&stack.pop().ok_or(RuntimeError::StackUnderflow)?)
-                                .ok_or(RuntimeError::InvalidInstruction)?,
-                        ) {
-                            // push true
-                            (false, true, Ordering::Less)
-                            | (true, false, Ordering::Equal)
-                            | (true, true, Ordering::Greater) => stack.push(true.into()),
-
-                            // push false
-                            _ => stack.push(false.into()),
-                        }
-                    }
-                }
+            run_path(&path, args)
+        }
+        Some(mode) => match mode.as_str() {
+            "run" => run_path(read_source(args.next())?.trim(), args),
+            "-e" => {
+                let path = args.next().ok_or(UsageError::MissingPath)?;
+                run_path(&path, args)
             }
-            4 => {
-                if !instruction[0] {
-                    if !instruction[1] {
-                        if !instruction[2] {
-                            if !instruction[3] {
-                                // index array or string
-                                let index = stack.pop().ok_or(RuntimeError::StackUnderflow)?;
-                                let value = stack.last().ok_or(RuntimeError::StackUnderflow)?;
-
-                                stack.push(match (value, index) {
-                                    (
-                                        StackValue::Value(Value::Array(array)),
-                                        StackValue::Value(Value::Integer(index)),
-                                    ) => array
-                                        .get(TryInto::<usize>::try_into(index)?)
-                                        .ok_or(RuntimeError::InvalidInstruction)?
-                                        .clone()
-                                        .into(),
-                                    (
-                                        StackValue::Value(Value::String(string)),
-                                        StackValue::Value(Value::Integer(index)),
-                                    ) => string
-                                        .chars()
-                                        .nth(TryInto::<usize>::try_into(index)?)
-                                        .ok_or(RuntimeError::InvalidInstruction)?
-                                        .into(),
-                                    _ => return Err(RuntimeError::InvalidInstruction.into()),
-                                });
-                            } else {
-                                // remove variable
-                                let index: BigUint = match instructions.get(ptr + 1) {
-                                    Some(instruction) => instruction,
-                                    None => return Err(RuntimeError::InvalidInstruction.into()),
-                                }
-                                .into();
-
-                                memory.remove(index);
-                            }
-                        } else {
-                            // jump to instruction if top of stack is boolean
-                            if instruction[3]
-                                == match stack.pop().ok_or(RuntimeError::StackUnderflow)? {
-                                    StackValue::Value(value) | StackValue::Argument(value) => {
-                                        value.into()
-                                    }
-                                    _ => return Err(RuntimeError::InvalidInstruction.into()),
-                                }
-                            {
-                                ptr = match instructions.get(ptr + 1) {
-                                    Some(instruction) => instruction,
-                                    None => return Err(RuntimeError::InvalidInstruction.into()),
-                                }
-                                .into();
-                                jumped = true;
-                            } else {
-                                ptr += 1;
-                            }
-                        }
-                    } else {
-                        if !instruction[2] {
-                            // push boolean
-                            stack.push(StackValue::Value(Value::Boolean(instruction[3])));
-                        } else {
-                            if !instruction[3] {
-                                // push array
-                                let length = match instructions.get(ptr + 1) {
-                                    Some(instruction) => instruction,
-                                    None => return Err(RuntimeError::InvalidInstruction.into()),
-                                }
-                                .into();
-
-                                let mut array = Vec::new();
-                                for _ in 0..length {
-                                    array.push(
-                                        stack.pop().ok_or(RuntimeError::StackUnderflow)?.into(),
-                                    );
-                                }
-                                stack.push(StackValue::Value(Value::Array(array)));
-                            } else {
-                                // spread array
-                                let array = match stack.pop().ok_or(RuntimeError::StackUnderflow)? {
-                                    StackValue::Value(Value::Array(array)) => array,
-                                    _ => return Err(RuntimeError::InvalidInstruction.into()),
-                                };
-                                for value in array.into_iter().rev() {
-                                    stack.push(StackValue::Value(value));
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    if instruction[1] && instruction[2] {
-                        let v = stack.pop().ok_or(RuntimeError::StackUnderflow)?.into();
-                        if !instruction[3] {
-                            // -top
-                            stack.push(
-                                match v {
-                                    Value::Boolean(v) => {
-                                        if v {
-                                            -BigInt::one()
-                                        } else {
-                                            BigInt::zero()
-                                        }
-                                    }
-                                    Value::Integer(v) => -v,
-                                    _ => return Err(RuntimeError::InvalidInstruction.into()),
-                                }
-                                .into(),
-                            );
-                        } else {
-                            // !top
-                            match v {
-                                Value::Boolean(v) => {
-                                    stack.push(StackValue::Value(Value::Boolean(!v)))
-                                }
-                                Value::Integer(v) => stack.push((!v).into()),
-                                _ => return Err(RuntimeError::InvalidInstruction.into()),
-                            }
-                        }
-                    } else {
-                        let v1 = stack.pop().ok_or(RuntimeError::StackUnderflow)?.into();
-                        let v2 = stack.pop().ok_or(RuntimeError::StackUnderflow)?.into();
-                        let res = match (instruction[1], instruction[2], instruction[3], v1, v2) {
-                            // +
-                            (false, false, false, Value::Boolean(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(((v1 as u8) + (v2 as u8)).into()))
-                            }
-                            (false, false, false, Value::Boolean(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer((v1 as u8) + v2))
-                            }
-                            (false, false, false, Value::Integer(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(v1 + (v2 as u8)))
-                            }
-                            (false, false, false, Value::Integer(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(v1 + v2))
-                            }
-
-                            // -
-                            (false, false, true, Value::Boolean(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(((v1 as u8) - (v2 as u8)).into()))
-                            }
-                            (false, false, true, Value::Boolean(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer((v1 as u8) - v2))
-                            }
-                            (false, false, true, Value::Integer(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(v1 - (v2 as u8)))
-                            }
-                            (false, false, true, Value::Integer(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(v1 - v2))
-                            }
-
-                            // *
-                            (false, true, false, Value::Boolean(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(((v1 as u8) * (v2 as u8)).into()))
-                            }
-                            (false, true, false, Value::Boolean(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer((v1 as u8) * v2))
-                            }
-                            (false, true, false, Value::Integer(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(v1 * (v2 as u8)))
-                            }
-                            (false, true, false, Value::Integer(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(v1 * v2))
-                            }
-
-                            // /
-                            (false, true, true, Value::Boolean(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(((v1 as u8) / (v2 as u8)).into()))
-                            }
-                            (false, true, true, Value::Boolean(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer((v1 as u8) / v2))
-                            }
-                            (false, true, true, Value::Integer(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(v1 / (v2 as u8)))
-                            }
-                            (false, true, true, Value::Integer(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(v1 / v2))
-                            }
-
-                            // %
-                            (true, false, false, Value::Boolean(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(((v1 as u8) % (v2 as u8)).into()))
-                            }
-                            (true, false, false, Value::Boolean(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer((v1 as u8) % v2))
-                            }
-                            (true, false, false, Value::Integer(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(v1 % (v2 as u8)))
-                            }
-                            (true, false, false, Value::Integer(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(v1 % v2))
-                            }
-
-                            // **
-                            (true, false, true, Value::Boolean(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(if !v1 && v2 {
-                                    BigInt::zero()
-                                } else {
-                                    BigInt::one()
-                                }))
-                            }
-                            (true, false, true, Value::Boolean(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(
-                                    if v1 { BigInt::one() } else { BigInt::zero() }.pow(TryInto::<
-                                        BigUint,
-                                    >::try_into(
-                                        v2
-                                    )?),
-                                ))
-                            }
-                            (true, false, true, Value::Integer(v1), Value::Boolean(v2)) => {
-                                StackValue::Value(Value::Integer(v1.pow(v2 as u8)))
-                            }
-                            (true, false, true, Value::Integer(v1), Value::Integer(v2)) => {
-                                StackValue::Value(Value::Integer(
-                                    v1.pow(TryInto::<BigUint>::try_into(v2)?),
-                                ))
-                            }
-
-                            _ => return Err(RuntimeError::InvalidInstruction.into()),
-                        };
-                        stack.push(res);
-                    }
-                }
+            "exec" => {
+                let Some(file) = args.next() else {
+                    return Err(UsageError::MissingExec.into());
+                };
+                let source = read_source(Some(file))?;
+                let path = source.trim();
+                // fail here rather than as whatever the binary would make of a bad name
+                Program::parse(path)?;
+                exec(path, args)
+            }
+            "help" | "-h" | "--help" => {
+                println!("{USAGE}");
+                Ok(())
+            }
+            "asm" => {
+                println!("{}", asm::assemble(&read_source(args.next())?)?);
+                Ok(())
             }
-            5 => {
-                if !instruction[0] {
-                    if !instruction[1] {
-                        if !instruction[2] {
-                            if !instruction[3] {
-                                if !instruction[4] {
-                                    // swap stack values, 0 is top
-                                    let i1: usize = stack
-                                        .len()
-                                        .checked_sub(Into::<usize>::into(
-                                            instructions
-                                                .get(ptr + 1)
-                                                .ok_or(RuntimeError::InvalidInstruction)?,
-                                        ))
-                                        .and_then(|x| x.checked_sub(1))
-                                        .ok_or(RuntimeError::InvalidInstruction)?;
-                                    let i2: usize = stack
-                                        .len()
-                                        .checked_sub(Into::<usize>::into(
-                                            instructions
-                                                .get(ptr + 2)
-                                                .ok_or(RuntimeError::InvalidInstruction)?,
-                                        ))
-                                        .and_then(|x| x.checked_sub(1))
-                                        .ok_or(RuntimeError::InvalidInstruction)?;
-                                    stack.swap(i1, i2);
-                                    ptr += 2;
-                                } else {
-                                    return Err(RuntimeError::InvalidInstruction.into());
-                                }
-                            } else {
-                                return Err(RuntimeError::InvalidInstruction.into());
-                            }
-                        } else {
-                            return Err(RuntimeError::InvalidInstruction.into());
-                        }
-                    } else {
-                        return Err(RuntimeError::InvalidInstruction.into());
-                    }
-                } else {
-                    return Err(RuntimeError::InvalidInstruction.into());
+            "disasm" => {
+                let path = read_source(args.next())?;
+                print!(
+                    "{}",
+                    disasm::disassemble(Program::parse(path.trim())?.instructions())
+                );
+                Ok(())
+            }
+            "optimize" => {
+                let program = Program::parse(read_source(args.next())?.trim())?;
+                println!("{}", program.optimized());
+                Ok(())
+            }
+            "check" => {
+                let program = Program::parse(read_source(args.next())?.trim())?;
+                let problems = check::check(program.instructions());
+                for problem in &problems {
+                    println!("{problem}");
+                }
+                let findings = analysis::analyze(program.code());
+                for problem in &findings {
+                    println!("{problem}");
+                }
+                match problems.len() + findings.len() {
+                    0 => Ok(()),
+                    count => Err(UsageError::Problems(count).into()),
                 }
             }
-            _ => {
-                return Err(RuntimeError::InvalidInstruction.into());
+            "repl" => repl::repl(),
+            "debug" => {
+                let Some(file) = args.next() else {
+                    return Err(UsageError::MissingFile(mode).into());
+                };
+                let program = Program::parse(fs::read_to_string(file)?.trim())?;
+                let mut interpreter = Interpreter::new();
+                interpreter.set_hook(Debugger::new());
+                interpreter.run(&program)
             }
+            "trace" => {
+                let log = args.next().ok_or(UsageError::MissingLog)?;
+                let program = Program::parse(read_source(args.next())?.trim())?;
+                let mut interpreter = Interpreter::new();
+                interpreter.set_hook(trace::Tracer::to(fs::File::create(log)?));
+                interpreter.run(&program)
+            }
+            _ => Err(UsageError::UnknownMode(mode).into()),
+        },
+        None => {
+            eprintln!("{USAGE}");
+            Err(UsageError::MissingMode.into())
         }
-
-        if !jumped {
-            ptr += 1;
-        }
-    }
-
-    Ok(())
-}
-
-fn main() -> Result<()> {
-    match env::consts::OS {
-        "windows" => {
-            let mut args = env::args();
-            args.next().expect("How did you even run this?");
-            run(parse(args.next().expect(
-                "Unfortunately for you Windows people, you need to pass a Linux path as the second argument",
-            ))?)
-        }
-        _ => run(parse(
-            env::args().next().expect("How did you even run this?"),
-        )?),
     }
 }
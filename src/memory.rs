@@ -4,15 +4,27 @@ use num_bigint::{BigUint, ToBigUint};
 
 use crate::{
     function::{BuiltInFunction, Function},
-    Value,
+    value::Value,
 };
 
-pub(crate) struct Memory {
+/// Global variable holding the arguments the program was run with, as an array of strings.
+pub const ARGS: u32 = 16;
+
+/// Global variables plus a stack of frames holding the locals of each running function call.
+#[derive(Debug, Clone)]
+pub struct Memory {
     memory: HashMap<BigUint, Value>,
+    frames: Vec<HashMap<BigUint, Value>>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Memory {
-    pub(crate) fn new() -> Memory {
+    pub fn new() -> Memory {
         let mut memory = HashMap::new();
 
         memory.insert(
@@ -23,10 +35,10 @@ impl Memory {
             1.to_biguint().unwrap(),
             Value::Function(Function::new_built_in(true, 0, BuiltInFunction::PrintLn)),
         );
-        // memory.insert(
-        //     2.to_biguint().unwrap(),
-        //     Value::Function(Function::new_built_in(false, 1, BuiltInFunction::Read)),
-        // );
+        memory.insert(
+            2.to_biguint().unwrap(),
+            Value::Function(Function::new_built_in(true, 0, BuiltInFunction::Read)),
+        );
         memory.insert(
             3.to_biguint().unwrap(),
             Value::Function(Function::new_built_in(false, 0, BuiltInFunction::ReadLn)),
@@ -59,19 +71,99 @@ impl Memory {
             10.to_biguint().unwrap(),
             Value::Function(Function::new_built_in(false, 1, BuiltInFunction::Pop)),
         );
+        memory.insert(
+            11.to_biguint().unwrap(),
+            Value::Function(Function::new_built_in(false, 2, BuiltInFunction::Get)),
+        );
+        memory.insert(
+            12.to_biguint().unwrap(),
+            Value::Function(Function::new_built_in(false, 3, BuiltInFunction::Set)),
+        );
+        memory.insert(
+            13.to_biguint().unwrap(),
+            Value::Function(Function::new_built_in(false, 2, BuiltInFunction::Remove)),
+        );
+        memory.insert(
+            14.to_biguint().unwrap(),
+            Value::Function(Function::new_built_in(false, 2, BuiltInFunction::Has)),
+        );
+        memory.insert(
+            15.to_biguint().unwrap(),
+            Value::Function(Function::new_built_in(false, 1, BuiltInFunction::Keys)),
+        );
+        memory.insert(ARGS.into(), Value::Array(Vec::new()));
 
-        Memory { memory }
+        Memory {
+            memory,
+            frames: Vec::new(),
+        }
     }
 
-    pub(crate) fn get(&self, index: BigUint) -> Option<&Value> {
+    /// Gets a local variable, falling back to the global one.
+    pub fn get(&self, index: BigUint) -> Option<&Value> {
+        match self.frames.last().and_then(|frame| frame.get(&index)) {
+            Some(value) => Some(value),
+            None => self.memory.get(&index),
+        }
+    }
+
+    /// Sets a local variable inside a function call, a global one otherwise.
+    pub fn set(&mut self, index: BigUint, value: Value) {
+        self.frames
+            .last_mut()
+            .unwrap_or(&mut self.memory)
+            .insert(index, value);
+    }
+
+    /// Removes a local variable inside a function call, a global one otherwise.
+    pub fn remove(&mut self, index: BigUint) {
+        self.frames
+            .last_mut()
+            .unwrap_or(&mut self.memory)
+            .remove(&index);
+    }
+
+    pub fn get_global(&self, index: BigUint) -> Option<&Value> {
         self.memory.get(&index)
     }
 
-    pub(crate) fn set(&mut self, index: BigUint, value: Value) {
+    pub fn set_global(&mut self, index: BigUint, value: Value) {
         self.memory.insert(index, value);
     }
 
-    pub(crate) fn remove(&mut self, index: BigUint) {
-        self.memory.remove(&index);
+    pub fn push_frame(&mut self, locals: HashMap<BigUint, Value>) {
+        self.frames.push(locals);
+    }
+
+    pub fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Number of function calls currently running.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The names of the variables every program starts with, the built-in functions and Args.
+    pub fn names(&self) -> HashMap<BigUint, String> {
+        let mut names = HashMap::from([(ARGS.into(), "Args".to_owned())]);
+        for (index, value) in &self.memory {
+            if let Value::Function(function) = value {
+                if function.is_built_in() {
+                    names.insert(index.clone(), function.built_in().to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// Iterates over the global variables.
+    pub fn iter(&self) -> impl Iterator<Item = (&BigUint, &Value)> {
+        self.memory.iter()
+    }
+
+    /// Iterates over the local variables of the innermost function call.
+    pub fn locals(&self) -> impl Iterator<Item = (&BigUint, &Value)> {
+        self.frames.last().into_iter().flatten()
     }
 }
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use crate::{function::Function, instruction::Instruction, opcode::Opcode};
+
+/// An opcode with its operands already read, jump targets are indices into the decoded ops.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Noop,
+    Duplicate,
+    Pop,
+    PushInteger(BigInt),
+    PopToVariable(BigUint),
+    PushVariable(BigUint),
+    PushString(String),
+    PushFloat(f64),
+    Call,
+    MakeArgument,
+    MakeOptional(BigUint),
+    Jump(usize),
+    Less,
+    Equal,
+    Greater,
+    Index,
+    RemoveVariable(BigUint),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    PushFalse,
+    PushTrue,
+    PushArray(usize),
+    SpreadArray,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    Negate,
+    Not,
+    Swap(usize, usize),
+    DefineFunction(Function),
+    Return,
+    PushGlobal(BigUint),
+    PopToGlobal(BigUint),
+    PushMap(usize),
+    PushNull,
+    IsNull,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    LessEqual,
+    GreaterEqual,
+    NotEqual,
+    And,
+    Or,
+    FloorDivide,
+    /// Anything that can't be decoded, fails when run so programs only break when they reach it.
+    Invalid,
+}
+
+impl Op {
+    /// `None` for [`Op::Invalid`].
+    pub fn opcode(&self) -> Option<Opcode> {
+        Some(match self {
+            Op::Noop => Opcode::Noop,
+            Op::Duplicate => Opcode::Duplicate,
+            Op::Pop => Opcode::Pop,
+            Op::PushInteger(_) => Opcode::PushInteger,
+            Op::PopToVariable(_) => Opcode::PopToVariable,
+            Op::PushVariable(_) => Opcode::PushVariable,
+            Op::PushString(_) => Opcode::PushString,
+            Op::PushFloat(_) => Opcode::PushFloat,
+            Op::Call => Opcode::Call,
+            Op::MakeArgument => Opcode::MakeArgument,
+            Op::MakeOptional(_) => Opcode::MakeOptional,
+            Op::Jump(_) => Opcode::Jump,
+            Op::Less => Opcode::Less,
+            Op::Equal => Opcode::Equal,
+            Op::Greater => Opcode::Greater,
+            Op::Index => Opcode::Index,
+            Op::RemoveVariable(_) => Opcode::RemoveVariable,
+            Op::JumpIfFalse(_) => Opcode::JumpIfFalse,
+            Op::JumpIfTrue(_) => Opcode::JumpIfTrue,
+            Op::PushFalse => Opcode::PushFalse,
+            Op::PushTrue => Opcode::PushTrue,
+            Op::PushArray(_) => Opcode::PushArray,
+            Op::SpreadArray => Opcode::SpreadArray,
+            Op::Add => Opcode::Add,
+            Op::Subtract => Opcode::Subtract,
+            Op::Multiply => Opcode::Multiply,
+            Op::Divide => Opcode::Divide,
+            Op::Remainder => Opcode::Remainder,
+            Op::Power => Opcode::Power,
+            Op::Negate => Opcode::Negate,
+            Op::Not => Opcode::Not,
+            Op::Swap(_, _) => Opcode::Swap,
+            Op::DefineFunction(_) => Opcode::DefineFunction,
+            Op::Return => Opcode::Return,
+            Op::PushGlobal(_) => Opcode::PushGlobal,
+            Op::PopToGlobal(_) => Opcode::PopToGlobal,
+            Op::PushMap(_) => Opcode::PushMap,
+            Op::PushNull => Opcode::PushNull,
+            Op::IsNull => Opcode::IsNull,
+            Op::BitAnd => Opcode::BitAnd,
+            Op::BitOr => Opcode::BitOr,
+            Op::BitXor => Opcode::BitXor,
+            Op::ShiftLeft => Opcode::ShiftLeft,
+            Op::ShiftRight => Opcode::ShiftRight,
+            Op::LessEqual => Opcode::LessEqual,
+            Op::GreaterEqual => Opcode::GreaterEqual,
+            Op::NotEqual => Opcode::NotEqual,
+            Op::And => Opcode::And,
+            Op::Or => Opcode::Or,
+            Op::FloorDivide => Opcode::FloorDivide,
+            Op::Invalid => return None,
+        })
+    }
+
+    /// How many characters the op takes up in a path, jump targets counted as 0.
+    pub(crate) fn path_len(&self) -> usize {
+        self.encode(|_| 0).iter().map(Instruction::path_len).sum()
+    }
+
+    /// The instructions for the op, `target` giving the instruction index of a jump target.
+    /// [`Op::Invalid`] becomes an instruction that isn't an opcode.
+    fn encode(&self, target: impl Fn(usize) -> usize) -> Vec<Instruction> {
+        let Some(opcode) = self.opcode() else {
+            return vec![vec![true; 6].into()];
+        };
+
+        let mut instructions = vec![opcode.code().to_vec().into()];
+        match self {
+            Op::PushInteger(int) => {
+                instructions.push((int.sign() == Sign::Plus).into());
+                instructions.push(int.magnitude().into());
+            }
+            Op::PopToVariable(index)
+            | Op::PushVariable(index)
+            | Op::MakeOptional(index)
+            | Op::RemoveVariable(index)
+            | Op::PushGlobal(index)
+            | Op::PopToGlobal(index) => instructions.push(index.into()),
+            Op::PushString(string) => {
+                instructions.push(string.chars().count().into());
+                for c in string.chars() {
+                    instructions.push(c.into());
+                }
+            }
+            Op::PushFloat(float) => {
+                instructions.push(float.is_sign_positive().into());
+                instructions
+                    .push((&BigUint::from_f64(float.abs().trunc()).unwrap_or_default()).into());
+                instructions.push(Instruction::from_fraction(float.abs().fract()));
+            }
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) => {
+                instructions.push(target(*to).into())
+            }
+            Op::PushArray(length) | Op::PushMap(length) => instructions.push((*length).into()),
+            Op::Swap(i1, i2) => {
+                instructions.push((*i1).into());
+                instructions.push((*i2).into());
+            }
+            Op::DefineFunction(function) => {
+                instructions.push(function.varargs().into());
+                instructions.push(function.arity().into());
+                instructions.push(function.instructions().len().into());
+                instructions.extend_from_slice(function.instructions());
+            }
+            _ => {}
+        }
+        instructions
+    }
+}
+
+/// Instructions along with the ops decoded from them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Code {
+    instructions: Vec<Instruction>,
+    ops: Vec<Op>,
+    /// Index of the instruction each op starts at.
+    positions: Vec<usize>,
+}
+
+impl Code {
+    pub fn decode(instructions: Vec<Instruction>) -> Code {
+        let mut ops = Vec::new();
+        let mut positions = Vec::new();
+        let mut jumps = HashMap::new();
+
+        let mut at = 0;
+        while at < instructions.len() {
+            let (op, width) = match Opcode::decode(&instructions[at]) {
+                Some(opcode) => {
+                    let width = opcode.width(&instructions, at);
+                    if width > instructions.len() - at {
+                        (Op::Invalid, instructions.len() - at)
+                    } else {
+                        let operands = &instructions[at + 1..at + width];
+                        if let Some(target) = target(opcode, operands) {
+                            jumps.insert(ops.len(), target);
+                        }
+                        (decode_op(opcode, operands).unwrap_or(Op::Invalid), width)
+                    }
+                }
+                None => (Op::Invalid, 1),
+            };
+            ops.push(op);
+            positions.push(at);
+            at += width;
+        }
+
+        for (op, target) in jumps {
+            let target = match target {
+                Some(target) if target < instructions.len() => {
+                    match positions.binary_search(&target) {
+                        Ok(target) => target,
+                        Err(_) => {
+                            ops[op] = Op::Invalid;
+                            continue;
+                        }
+                    }
+                }
+                _ => ops.len(),
+            };
+            match &mut ops[op] {
+                Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) => *to = target,
+                _ => unreachable!(),
+            }
+        }
+
+        Code {
+            instructions,
+            ops,
+            positions,
+        }
+    }
+
+    /// Builds the instructions for `ops`, whose jump targets are indices into `ops`.
+    pub fn encode(ops: Vec<Op>) -> Code {
+        let mut positions = Vec::with_capacity(ops.len());
+        let mut end = 0;
+        for op in &ops {
+            positions.push(end);
+            end += op.encode(|_| 0).len();
+        }
+
+        let mut instructions = Vec::with_capacity(end);
+        for op in &ops {
+            instructions.extend(op.encode(|to| positions.get(to).copied().unwrap_or(end)));
+        }
+
+        Code {
+            instructions,
+            ops,
+            positions,
+        }
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Index of the instruction the op at `op` starts at.
+    pub fn position(&self, op: usize) -> usize {
+        self.positions
+            .get(op)
+            .copied()
+            .unwrap_or(self.instructions.len())
+    }
+}
+
+/// The instruction index a jump goes to, `None` inside when it is too big to be one.
+fn target(opcode: Opcode, operands: &[Instruction]) -> Option<Option<usize>> {
+    match opcode {
+        Opcode::Jump | Opcode::JumpIfFalse | Opcode::JumpIfTrue => {
+            Some(BigUint::from(&operands[0]).to_usize())
+        }
+        _ => None,
+    }
+}
+
+fn decode_op(opcode: Opcode, operands: &[Instruction]) -> Option<Op> {
+    let identifier = || BigUint::from(&operands[0]);
+    let number = |i: usize| BigUint::from(&operands[i]).to_usize();
+
+    Some(match opcode {
+        Opcode::Noop => Op::Noop,
+        Opcode::Duplicate => Op::Duplicate,
+        Opcode::Pop => Op::Pop,
+        Opcode::PushInteger => {
+            let int = BigInt::from(BigUint::from(&operands[1]));
+            Op::PushInteger(if bool::from(&operands[0]) { int } else { -int })
+        }
+        Opcode::PopToVariable => Op::PopToVariable(identifier()),
+        Opcode::PushVariable => Op::PushVariable(identifier()),
+        Opcode::PushString => {
+            let mut string = String::new();
+            for instruction in &operands[1..] {
+                string.push(Into::<anyhow::Result<char>>::into(instruction).ok()?);
+            }
+            Op::PushString(string)
+        }
+        Opcode::PushFloat => {
+            let float = BigUint::from(&operands[1])
+                .to_f64()
+                .unwrap_or(f64::INFINITY)
+                + operands[2].fraction();
+            Op::PushFloat(if bool::from(&operands[0]) {
+                float
+            } else {
+                -float
+            })
+        }
+        Opcode::Call => Op::Call,
+        Opcode::MakeArgument => Op::MakeArgument,
+        Opcode::MakeOptional => Op::MakeOptional(identifier()),
+        Opcode::Jump => Op::Jump(0),
+        Opcode::Less => Op::Less,
+        Opcode::Equal => Op::Equal,
+        Opcode::Greater => Op::Greater,
+        Opcode::Index => Op::Index,
+        Opcode::RemoveVariable => Op::RemoveVariable(identifier()),
+        Opcode::JumpIfFalse => Op::JumpIfFalse(0),
+        Opcode::JumpIfTrue => Op::JumpIfTrue(0),
+        Opcode::PushFalse => Op::PushFalse,
+        Opcode::PushTrue => Op::PushTrue,
+        Opcode::PushArray => Op::PushArray(number(0)?),
+        Opcode::SpreadArray => Op::SpreadArray,
+        Opcode::Add => Op::Add,
+        Opcode::Subtract => Op::Subtract,
+        Opcode::Multiply => Op::Multiply,
+        Opcode::Divide => Op::Divide,
+        Opcode::Remainder => Op::Remainder,
+        Opcode::Power => Op::Power,
+        Opcode::Negate => Op::Negate,
+        Opcode::Not => Op::Not,
+        Opcode::Swap => Op::Swap(number(0)?, number(1)?),
+        Opcode::DefineFunction => Op::DefineFunction(Function::new(
+            bool::from(&operands[0]),
+            number(1)?,
+            operands[3..].to_vec(),
+        )),
+        Opcode::Return => Op::Return,
+        Opcode::PushGlobal => Op::PushGlobal(identifier()),
+        Opcode::PopToGlobal => Op::PopToGlobal(identifier()),
+        Opcode::PushMap => Op::PushMap(number(0)?),
+        Opcode::PushNull => Op::PushNull,
+        Opcode::IsNull => Op::IsNull,
+        Opcode::BitAnd => Op::BitAnd,
+        Opcode::BitOr => Op::BitOr,
+        Opcode::BitXor => Op::BitXor,
+        Opcode::ShiftLeft => Op::ShiftLeft,
+        Opcode::ShiftRight => Op::ShiftRight,
+        Opcode::LessEqual => Op::LessEqual,
+        Opcode::GreaterEqual => Op::GreaterEqual,
+        Opcode::NotEqual => Op::NotEqual,
+        Opcode::And => Op::And,
+        Opcode::Or => Op::Or,
+        Opcode::FloorDivide => Op::FloorDivide,
+    })
+}
@@ -0,0 +1,376 @@
+use num_bigint::BigUint;
+
+use crate::instruction::Instruction;
+
+/// Every instruction in the spec, told apart by its parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Noop,
+    Duplicate,
+    Pop,
+    PushInteger,
+    PopToVariable,
+    PushVariable,
+    PushString,
+    PushFloat,
+    Call,
+    MakeArgument,
+    MakeOptional,
+    Jump,
+    Less,
+    Equal,
+    Greater,
+    Index,
+    RemoveVariable,
+    JumpIfFalse,
+    JumpIfTrue,
+    PushFalse,
+    PushTrue,
+    PushArray,
+    SpreadArray,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    Negate,
+    Not,
+    Swap,
+    DefineFunction,
+    Return,
+    PushGlobal,
+    PopToGlobal,
+    PushMap,
+    PushNull,
+    IsNull,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    LessEqual,
+    GreaterEqual,
+    NotEqual,
+    And,
+    Or,
+    FloorDivide,
+}
+
+/// What the instructions following an opcode mean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Boolean,
+    Number,
+    Fraction,
+    Identifier,
+    /// Index of the instruction to jump to.
+    Target,
+    /// A length followed by that many characters.
+    String,
+    /// A length followed by that many instructions.
+    Body,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 50] = [
+        Opcode::Noop,
+        Opcode::Duplicate,
+        Opcode::Pop,
+        Opcode::PushInteger,
+        Opcode::PopToVariable,
+        Opcode::PushVariable,
+        Opcode::PushString,
+        Opcode::PushFloat,
+        Opcode::Call,
+        Opcode::MakeArgument,
+        Opcode::MakeOptional,
+        Opcode::Jump,
+        Opcode::Less,
+        Opcode::Equal,
+        Opcode::Greater,
+        Opcode::Index,
+        Opcode::RemoveVariable,
+        Opcode::JumpIfFalse,
+        Opcode::JumpIfTrue,
+        Opcode::PushFalse,
+        Opcode::PushTrue,
+        Opcode::PushArray,
+        Opcode::SpreadArray,
+        Opcode::Add,
+        Opcode::Subtract,
+        Opcode::Multiply,
+        Opcode::Divide,
+        Opcode::Remainder,
+        Opcode::Power,
+        Opcode::Negate,
+        Opcode::Not,
+        Opcode::Swap,
+        Opcode::DefineFunction,
+        Opcode::Return,
+        Opcode::PushGlobal,
+        Opcode::PopToGlobal,
+        Opcode::PushMap,
+        Opcode::PushNull,
+        Opcode::IsNull,
+        Opcode::BitAnd,
+        Opcode::BitOr,
+        Opcode::BitXor,
+        Opcode::ShiftLeft,
+        Opcode::ShiftRight,
+        Opcode::LessEqual,
+        Opcode::GreaterEqual,
+        Opcode::NotEqual,
+        Opcode::And,
+        Opcode::Or,
+        Opcode::FloorDivide,
+    ];
+
+    pub fn decode(instruction: &Instruction) -> Option<Opcode> {
+        Opcode::ALL
+            .into_iter()
+            .find(|opcode| opcode.code() == instruction.parts())
+    }
+
+    /// The parts making up the instruction, `false` for `.` and `true` for `..`.
+    pub fn code(self) -> &'static [bool] {
+        match self {
+            Opcode::Noop => &[],
+            Opcode::Duplicate => &[false],
+            Opcode::Pop => &[true],
+            Opcode::PushInteger => &[false, false],
+            Opcode::PopToVariable => &[false, true],
+            Opcode::PushVariable => &[true, false],
+            Opcode::PushString => &[true, true],
+            Opcode::PushFloat => &[false, false, false],
+            Opcode::Call => &[false, false, true],
+            Opcode::MakeArgument => &[false, true, false],
+            Opcode::MakeOptional => &[false, true, true],
+            Opcode::Jump => &[true, false, false],
+            Opcode::Less => &[true, false, true],
+            Opcode::Equal => &[true, true, false],
+            Opcode::Greater => &[true, true, true],
+            Opcode::Index => &[false, false, false, false],
+            Opcode::RemoveVariable => &[false, false, false, true],
+            Opcode::JumpIfFalse => &[false, false, true, false],
+            Opcode::JumpIfTrue => &[false, false, true, true],
+            Opcode::PushFalse => &[false, true, false, false],
+            Opcode::PushTrue => &[false, true, false, true],
+            Opcode::PushArray => &[false, true, true, false],
+            Opcode::SpreadArray => &[false, true, true, true],
+            Opcode::Add => &[true, false, false, false],
+            Opcode::Subtract => &[true, false, false, true],
+            Opcode::Multiply => &[true, false, true, false],
+            Opcode::Divide => &[true, false, true, true],
+            Opcode::Remainder => &[true, true, false, false],
+            Opcode::Power => &[true, true, false, true],
+            Opcode::Negate => &[true, true, true, false],
+            Opcode::Not => &[true, true, true, true],
+            Opcode::Swap => &[false, false, false, false, false],
+            Opcode::DefineFunction => &[false, false, false, false, true],
+            Opcode::Return => &[false, false, false, true, false],
+            Opcode::PushGlobal => &[false, false, false, true, true],
+            Opcode::PopToGlobal => &[false, false, true, false, false],
+            Opcode::PushMap => &[false, false, true, true, true],
+            Opcode::PushNull => &[false, false, true, false, true],
+            Opcode::IsNull => &[false, false, true, true, false],
+            Opcode::BitAnd => &[true, false, false, false, false],
+            Opcode::BitOr => &[true, false, false, false, true],
+            Opcode::BitXor => &[true, false, false, true, false],
+            Opcode::ShiftLeft => &[true, false, false, true, true],
+            Opcode::ShiftRight => &[true, false, true, false, false],
+            Opcode::LessEqual => &[true, false, true, false, true],
+            Opcode::GreaterEqual => &[true, false, true, true, false],
+            Opcode::NotEqual => &[true, false, true, true, true],
+            Opcode::And => &[true, true, false, false, false],
+            Opcode::Or => &[true, true, false, false, true],
+            Opcode::FloorDivide => &[true, true, false, true, false],
+        }
+    }
+
+    /// The name the spec gives the instruction.
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::Noop => "noop",
+            Opcode::Duplicate => "duplicate top of stack",
+            Opcode::Pop => "pop top of stack",
+            Opcode::PushInteger => "push integer",
+            Opcode::PopToVariable => "pop to variable",
+            Opcode::PushVariable => "push variable",
+            Opcode::PushString => "push string",
+            Opcode::PushFloat => "push float",
+            Opcode::Call => "call function",
+            Opcode::MakeArgument => "make argument",
+            Opcode::MakeOptional => "make optional argument",
+            Opcode::Jump => "jump to instruction",
+            Opcode::Less => "push top < top2",
+            Opcode::Equal => "push top == top2",
+            Opcode::Greater => "push top > top2",
+            Opcode::Index => "index array or string",
+            Opcode::RemoveVariable => "remove variable",
+            Opcode::JumpIfFalse => "jump to instruction if top of stack is false",
+            Opcode::JumpIfTrue => "jump to instruction if top of stack is true",
+            Opcode::PushFalse => "push false",
+            Opcode::PushTrue => "push true",
+            Opcode::PushArray => "push array",
+            Opcode::SpreadArray => "spread array",
+            Opcode::Add => "top + top2",
+            Opcode::Subtract => "top - top2",
+            Opcode::Multiply => "top * top2",
+            Opcode::Divide => "top / top2",
+            Opcode::Remainder => "top % top2",
+            Opcode::Power => "top ** top2",
+            Opcode::Negate => "-top",
+            Opcode::Not => "!top",
+            Opcode::Swap => "swap stack values",
+            Opcode::DefineFunction => "define function",
+            Opcode::Return => "return top of stack",
+            Opcode::PushGlobal => "push global variable",
+            Opcode::PopToGlobal => "pop to global variable",
+            Opcode::PushMap => "push map",
+            Opcode::PushNull => "push null",
+            Opcode::IsNull => "push top is null",
+            Opcode::BitAnd => "top & top2",
+            Opcode::BitOr => "top | top2",
+            Opcode::BitXor => "top ^ top2",
+            Opcode::ShiftLeft => "top << top2",
+            Opcode::ShiftRight => "top >> top2",
+            Opcode::LessEqual => "push top <= top2",
+            Opcode::GreaterEqual => "push top >= top2",
+            Opcode::NotEqual => "push top != top2",
+            Opcode::And => "top and top2",
+            Opcode::Or => "top or top2",
+            Opcode::FloorDivide => "top // top2",
+        }
+    }
+
+    /// The name used by the assembler, `push` covers every literal.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Noop => "nop",
+            Opcode::Duplicate => "dup",
+            Opcode::Pop => "pop",
+            Opcode::PushInteger
+            | Opcode::PushString
+            | Opcode::PushFloat
+            | Opcode::PushFalse
+            | Opcode::PushTrue
+            | Opcode::PushNull => "push",
+            Opcode::PopToVariable => "store",
+            Opcode::PushVariable => "load",
+            Opcode::Call => "call",
+            Opcode::MakeArgument => "arg",
+            Opcode::MakeOptional => "opt",
+            Opcode::Jump => "jump",
+            Opcode::Less => "lt",
+            Opcode::Equal => "eq",
+            Opcode::Greater => "gt",
+            Opcode::Index => "index",
+            Opcode::RemoveVariable => "del",
+            Opcode::JumpIfFalse => "jumpifnot",
+            Opcode::JumpIfTrue => "jumpif",
+            Opcode::PushArray => "array",
+            Opcode::SpreadArray => "spread",
+            Opcode::Add => "add",
+            Opcode::Subtract => "sub",
+            Opcode::Multiply => "mul",
+            Opcode::Divide => "div",
+            Opcode::Remainder => "mod",
+            Opcode::Power => "pow",
+            Opcode::Negate => "neg",
+            Opcode::Not => "not",
+            Opcode::Swap => "swap",
+            Opcode::DefineFunction => "func",
+            Opcode::Return => "ret",
+            Opcode::PushGlobal => "gload",
+            Opcode::PopToGlobal => "gstore",
+            Opcode::PushMap => "map",
+            Opcode::IsNull => "isnull",
+            Opcode::BitAnd => "band",
+            Opcode::BitOr => "bor",
+            Opcode::BitXor => "bxor",
+            Opcode::ShiftLeft => "shl",
+            Opcode::ShiftRight => "shr",
+            Opcode::LessEqual => "le",
+            Opcode::GreaterEqual => "ge",
+            Opcode::NotEqual => "ne",
+            Opcode::And => "and",
+            Opcode::Or => "or",
+            Opcode::FloorDivide => "fdiv",
+        }
+    }
+
+    pub fn operands(self) -> &'static [Operand] {
+        match self {
+            Opcode::PushInteger => &[Operand::Boolean, Operand::Number],
+            Opcode::PopToVariable
+            | Opcode::PushVariable
+            | Opcode::MakeOptional
+            | Opcode::RemoveVariable
+            | Opcode::PushGlobal
+            | Opcode::PopToGlobal => &[Operand::Identifier],
+            Opcode::PushString => &[Operand::String],
+            Opcode::PushFloat => &[Operand::Boolean, Operand::Number, Operand::Fraction],
+            Opcode::Jump | Opcode::JumpIfFalse | Opcode::JumpIfTrue => &[Operand::Target],
+            Opcode::PushArray | Opcode::PushMap => &[Operand::Number],
+            Opcode::Swap => &[Operand::Number, Operand::Number],
+            Opcode::DefineFunction => &[Operand::Boolean, Operand::Number, Operand::Body],
+            _ => &[],
+        }
+    }
+
+    /// How many instructions the opcode at `at` takes up along with its operands,
+    /// assuming none are missing.
+    pub fn width(self, instructions: &[Instruction], at: usize) -> usize {
+        let mut width = 1;
+        for operand in self.operands() {
+            if let (Operand::String | Operand::Body, Some(length)) =
+                (operand, instructions.get(at + width))
+            {
+                width = width.saturating_add(length.into());
+            }
+            width = width.saturating_add(1);
+        }
+        width
+    }
+
+    /// Shows the operands of the instruction at `at`, stopping at the first missing one.
+    pub fn format_operands(self, instructions: &[Instruction], at: usize) -> Vec<String> {
+        let mut operands = Vec::new();
+        let mut next = at + 1;
+        for operand in self.operands() {
+            let Some(instruction) = instructions.get(next) else {
+                break;
+            };
+            next += 1;
+
+            operands.push(match operand {
+                Operand::Boolean => bool::from(instruction).to_string(),
+                Operand::Number | Operand::Identifier | Operand::Target => {
+                    BigUint::from(instruction).to_string()
+                }
+                Operand::Fraction => instruction.fraction().to_string(),
+                Operand::String => {
+                    let length: usize = instruction.into();
+                    let string: String = instructions
+                        .iter()
+                        .skip(next)
+                        .take(length)
+                        .map(|instruction| {
+                            Into::<anyhow::Result<char>>::into(instruction)
+                                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        })
+                        .collect();
+                    next = next.saturating_add(length);
+                    format!("{string:?}")
+                }
+                Operand::Body => {
+                    let length: usize = instruction.into();
+                    next = next.saturating_add(length);
+                    format!("{length} instructions")
+                }
+            });
+        }
+        operands
+    }
+}
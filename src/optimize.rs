@@ -0,0 +1,431 @@
+//! Peephole optimizer over decoded ops.
+//!
+//! Constant arithmetic and comparisons are folded, pushes that are popped right away are
+//! dropped, jumps to jumps go straight to where they end up and unreachable ops are removed.
+//! Code holding ops that can't be decoded is left as is, since its jumps can't be trusted.
+
+use std::collections::VecDeque;
+
+use num_bigint::BigInt;
+
+use crate::{
+    arithmetic::{self, BinaryOperator},
+    function::Function,
+    instruction::Instruction,
+    op::{Code, Op},
+    value::Value,
+};
+
+/// Exponents, shifts and repetition counts above this aren't folded, so optimizing never has to
+/// build huge values.
+const MAX_FOLDED_COUNT: u32 = 64;
+
+fn constant(op: &Op) -> Option<Value> {
+    Some(match op {
+        Op::PushInteger(int) => Value::Integer(int.clone()),
+        Op::PushFloat(float) => Value::Float(*float),
+        Op::PushString(string) => Value::String(string.clone()),
+        Op::PushFalse => Value::Boolean(false),
+        Op::PushTrue => Value::Boolean(true),
+        Op::PushNull => Value::Null,
+        _ => return None,
+    })
+}
+
+/// The op pushing `value`, if it has one.
+fn push(value: Value) -> Option<Op> {
+    Some(match value {
+        Value::Integer(int) => Op::PushInteger(int),
+        Value::Float(float) if float.is_finite() => Op::PushFloat(float),
+        Value::String(string) => Op::PushString(string),
+        Value::Boolean(false) => Op::PushFalse,
+        Value::Boolean(true) => Op::PushTrue,
+        Value::Null => Op::PushNull,
+        _ => return None,
+    })
+}
+
+/// Evaluates `op` on top (`v1`) and top2 (`v2`), `None` when it would fail or can't be pushed.
+fn fold_binary(op: &Op, v1: Value, v2: Value) -> Option<Op> {
+    if let Some(operator) = BinaryOperator::of(op) {
+        let count = match (operator, &v1, &v2) {
+            (
+                BinaryOperator::Power | BinaryOperator::ShiftLeft,
+                Value::Integer(_),
+                Value::Integer(count),
+            )
+            | (
+                BinaryOperator::Multiply,
+                Value::String(_) | Value::Array(_),
+                Value::Integer(count),
+            )
+            | (
+                BinaryOperator::Multiply,
+                Value::Integer(count),
+                Value::String(_) | Value::Array(_),
+            ) => Some(count),
+            _ => None,
+        };
+        if count.is_some_and(|count| *count > BigInt::from(MAX_FOLDED_COUNT)) {
+            return None;
+        }
+        return push(arithmetic::binary(operator, v1, v2).ok()?);
+    }
+
+    push(Value::Boolean(match op {
+        Op::Equal => arithmetic::equal(&v1, &v2),
+        Op::NotEqual => !arithmetic::equal(&v1, &v2),
+        op => arithmetic::compare(op, v1.partial_cmp(&v2)?)?,
+    }))
+}
+
+fn fold_unary(op: &Op, value: Value) -> Option<Op> {
+    push(match op {
+        Op::Negate => arithmetic::negate(value).ok()?,
+        Op::Not => arithmetic::not(value).ok()?,
+        Op::IsNull => Value::Boolean(value == Value::Null),
+        _ => return None,
+    })
+}
+
+/// Whether `op` is no longer than the `replaced` ops, folding can build constants that take
+/// more characters than the ops computing them.
+fn fits(op: &Op, replaced: &[Op]) -> bool {
+    op.path_len() <= replaced.iter().map(Op::path_len).sum()
+}
+
+/// How many characters `code` takes up in a path.
+fn path_len(code: &Code) -> usize {
+    code.instructions().iter().map(Instruction::path_len).sum()
+}
+
+fn retarget(op: &mut Op, f: impl Fn(usize) -> usize) {
+    if let Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) = op {
+        *to = f(*to);
+    }
+}
+
+fn targets(ops: &[Op]) -> Vec<bool> {
+    let mut targeted = vec![false; ops.len() + 1];
+    for op in ops {
+        if let Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) = op {
+            targeted[(*to).min(ops.len())] = true;
+        }
+    }
+    targeted
+}
+
+/// Drops the ops that aren't kept, jumps to a dropped op go to the next kept one.
+fn remove(ops: Vec<Op>, keep: &[bool]) -> Vec<Op> {
+    let mut moved = Vec::with_capacity(ops.len() + 1);
+    let mut next = 0;
+    for kept in keep {
+        moved.push(next);
+        if *kept {
+            next += 1;
+        }
+    }
+    moved.push(next);
+
+    let len = ops.len();
+    ops.into_iter()
+        .zip(keep)
+        .filter(|(_, kept)| **kept)
+        .map(|(mut op, _)| {
+            retarget(&mut op, |to| moved[to.min(len)]);
+            op
+        })
+        .collect()
+}
+
+/// Rewrites short patterns in place, none of them may have a jump into their middle.
+fn peephole(mut ops: Vec<Op>) -> (Vec<Op>, bool) {
+    let targeted = targets(&ops);
+    let mut keep = vec![true; ops.len()];
+    let mut changed = false;
+
+    let len = ops.len();
+    let mut i = 0;
+    while i < len {
+        let free = |n: usize| i + n < len && !targeted[i + 1..=i + n].contains(&true);
+        let first = constant(&ops[i]);
+
+        let mut width = 0;
+        if let (Some(v2), true) = (&first, free(2)) {
+            if let Some(v1) = constant(&ops[i + 1]) {
+                let folded = fold_binary(&ops[i + 2], v1, v2.clone());
+                if let Some(op) = folded.filter(|op| fits(op, &ops[i..i + 3])) {
+                    ops[i] = op;
+                    width = 3;
+                }
+            }
+        }
+        if width == 0 && free(1) {
+            let pushes = first.is_some() || matches!(ops[i], Op::Duplicate | Op::DefineFunction(_));
+            match (first, &ops[i + 1]) {
+                (Some(value), op @ (Op::Negate | Op::Not | Op::IsNull)) => {
+                    let folded = fold_unary(op, value);
+                    if let Some(op) = folded.filter(|op| fits(op, &ops[i..i + 2])) {
+                        ops[i] = op;
+                        width = 2;
+                    }
+                }
+                (_, Op::Pop) if pushes => {
+                    keep[i] = false;
+                    width = 2;
+                }
+                (Some(value), Op::JumpIfFalse(to) | Op::JumpIfTrue(to)) => {
+                    if let Ok(condition) = bool::try_from(value) {
+                        if matches!(ops[i + 1], Op::JumpIfTrue(_)) == condition {
+                            ops[i] = Op::Jump(*to);
+                        } else {
+                            keep[i] = false;
+                        }
+                        width = 2;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match width {
+            0 => {
+                if matches!(ops[i], Op::Noop) || ops[i] == Op::Jump(i + 1) {
+                    keep[i] = false;
+                    changed = true;
+                }
+                i += 1;
+            }
+            _ => {
+                for kept in &mut keep[i + 1..i + width] {
+                    *kept = false;
+                }
+                changed = true;
+                i += width;
+            }
+        }
+    }
+
+    (remove(ops, &keep), changed)
+}
+
+/// Points jumps at where a chain of jumps ends up.
+fn thread(mut ops: Vec<Op>) -> (Vec<Op>, bool) {
+    let mut changed = false;
+    for i in 0..ops.len() {
+        let (Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to)) = ops[i] else {
+            continue;
+        };
+        let mut end = to;
+        // a chain longer than the code loops forever, which is left alone
+        for _ in 0..ops.len() {
+            match ops.get(end) {
+                Some(Op::Jump(next)) if *next != end => end = *next,
+                _ => break,
+            }
+        }
+        if end != to {
+            retarget(&mut ops[i], |_| end);
+            changed = true;
+        }
+    }
+    (ops, changed)
+}
+
+/// Drops the ops no path from the start reaches.
+fn prune(ops: Vec<Op>) -> (Vec<Op>, bool) {
+    let mut reached = vec![false; ops.len()];
+    let mut pending = VecDeque::from([0]);
+    while let Some(i) = pending.pop_front() {
+        if i >= ops.len() || reached[i] {
+            continue;
+        }
+        reached[i] = true;
+        match ops[i] {
+            Op::Jump(to) => pending.push_back(to),
+            Op::JumpIfFalse(to) | Op::JumpIfTrue(to) => pending.extend([to, i + 1]),
+            Op::Return | Op::Invalid => {}
+            _ => pending.push_back(i + 1),
+        }
+    }
+
+    let changed = reached.contains(&false);
+    (remove(ops, &reached), changed)
+}
+
+/// Gives back shorter code doing the same as `code`, function bodies included, or `code` itself
+/// when nothing gets shorter.
+pub fn optimize(code: &Code) -> Code {
+    if code.ops().contains(&Op::Invalid) {
+        return code.clone();
+    }
+
+    let mut ops: Vec<Op> = code
+        .ops()
+        .iter()
+        .map(|op| match op {
+            Op::DefineFunction(function) => Op::DefineFunction(Function::new(
+                function.varargs(),
+                function.arity(),
+                optimize(function.code()).instructions().to_vec(),
+            )),
+            op => op.clone(),
+        })
+        .collect();
+
+    loop {
+        let (folded, peeped) = peephole(ops);
+        let (threaded, threads) = thread(folded);
+        let (pruned, pruned_any) = prune(threaded);
+        ops = pruned;
+        if !(peeped || threads || pruned_any) {
+            break;
+        }
+    }
+
+    let optimized = Code::encode(ops);
+    // retargeted jumps can take more characters than before
+    if path_len(&optimized) > path_len(code) {
+        return code.clone();
+    }
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+
+    use super::*;
+
+    fn int(n: i32) -> Op {
+        Op::PushInteger(n.into())
+    }
+
+    fn optimized(ops: Vec<Op>) -> Vec<Op> {
+        optimize(&Code::encode(ops)).ops().to_vec()
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(optimized(vec![int(2), int(3), Op::Add]), vec![int(5)]);
+        assert_eq!(
+            optimized(vec![Op::PushTrue, Op::Not, Op::PushNull, Op::IsNull]),
+            vec![Op::PushFalse, Op::PushTrue]
+        );
+    }
+
+    #[test]
+    fn folds_null_equality() {
+        assert_eq!(
+            optimized(vec![int(1), Op::PushNull, Op::Equal]),
+            vec![Op::PushFalse]
+        );
+        assert_eq!(
+            optimized(vec![Op::PushNull, Op::PushNull, Op::NotEqual]),
+            vec![Op::PushFalse]
+        );
+    }
+
+    #[test]
+    fn leaves_pushes_that_are_stored() {
+        let ops = vec![int(1), int(2), Op::PopToVariable(BigUint::from(7u8))];
+        assert_eq!(optimized(ops.clone()), ops);
+    }
+
+    #[test]
+    fn leaves_huge_counts() {
+        let ops = vec![int(1000), int(2), Op::Power];
+        assert_eq!(optimized(ops.clone()), ops);
+    }
+
+    #[test]
+    fn leaves_folds_that_would_be_longer() {
+        let ops = vec![
+            Op::PushString("abcdefghij".to_owned()),
+            int(64),
+            Op::Multiply,
+        ];
+        assert_eq!(optimized(ops.clone()), ops);
+    }
+
+    #[test]
+    fn never_gets_longer() {
+        let program = crate::asm::assemble(
+            "push \"abcdefghij\"\npush 64\nmul\npush 1\npush 2\nadd\npush 0\nneg",
+        )
+        .unwrap();
+        assert!(program.optimized().to_string().len() <= program.to_string().len());
+    }
+
+    #[test]
+    fn does_not_fold_across_jump_targets() {
+        let ops = vec![Op::JumpIfTrue(2), int(1), int(2), Op::Add];
+        let (peeped, changed) = peephole(ops.clone());
+        assert_eq!(peeped, ops);
+        assert!(!changed);
+    }
+
+    #[test]
+    fn drops_pushes_that_are_popped() {
+        assert_eq!(
+            optimized(vec![int(1), Op::Pop, Op::Return]),
+            vec![Op::Return]
+        );
+    }
+
+    #[test]
+    fn resolves_constant_branches() {
+        assert_eq!(
+            optimized(vec![Op::PushTrue, Op::JumpIfTrue(3), int(1), Op::Return]),
+            vec![Op::Return]
+        );
+        assert_eq!(
+            optimized(vec![Op::PushFalse, Op::JumpIfTrue(3), int(1), Op::Return]),
+            vec![int(1), Op::Return]
+        );
+    }
+
+    #[test]
+    fn threads_jump_chains() {
+        let (threaded, changed) = thread(vec![Op::Jump(2), Op::Return, Op::Jump(3), Op::Return]);
+        assert_eq!(
+            threaded,
+            vec![Op::Jump(3), Op::Return, Op::Jump(3), Op::Return]
+        );
+        assert!(changed);
+    }
+
+    #[test]
+    fn leaves_jump_loops() {
+        let ops = vec![Op::Jump(1), Op::Jump(0)];
+        let (threaded, changed) = thread(ops.clone());
+        assert_eq!(threaded, ops);
+        assert!(!changed);
+    }
+
+    #[test]
+    fn prunes_unreachable_ops() {
+        let (pruned, changed) = prune(vec![Op::Jump(2), int(1), Op::Return, int(2)]);
+        assert_eq!(pruned, vec![Op::Jump(1), Op::Return]);
+        assert!(changed);
+    }
+
+    #[test]
+    fn retargets_jumps_past_removed_ops() {
+        let ops = vec![
+            Op::JumpIfFalse(2),
+            Op::Jump(4),
+            Op::Noop,
+            Op::Duplicate,
+            int(1),
+        ];
+        let kept = remove(ops, &[true, true, false, true, false]);
+        assert_eq!(kept, vec![Op::JumpIfFalse(2), Op::Jump(3), Op::Duplicate]);
+    }
+
+    #[test]
+    fn leaves_undecodable_code() {
+        let ops = vec![int(1), int(2), Op::Add, Op::Invalid];
+        assert_eq!(optimized(ops.clone()), ops);
+    }
+}
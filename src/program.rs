@@ -0,0 +1,91 @@
+use std::fmt::{self, Display};
+
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::{
+    instruction::{Instruction, ParseInstruction, ParsedInstruction},
+    op::Code,
+    optimize,
+};
+
+#[derive(Debug, Error)]
+pub enum SyntaxError {
+    #[error("Doesn't start at /")]
+    NotNoop,
+}
+
+/// A parsed path, ready to be run by an [`Interpreter`](crate::Interpreter).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Program {
+    code: Code,
+}
+
+impl Program {
+    pub fn parse(path: &str) -> Result<Program> {
+        Ok(parse(path)?.into())
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        self.code.instructions()
+    }
+
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+
+    /// A shorter program doing the same, see [`optimize`](crate::optimize).
+    pub fn optimized(&self) -> Program {
+        optimize::optimize(&self.code).into()
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/")?;
+        for instruction in self.instructions() {
+            write!(f, "{instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Code> for Program {
+    fn from(code: Code) -> Self {
+        Program { code }
+    }
+}
+
+impl From<Vec<Instruction>> for Program {
+    fn from(instructions: Vec<Instruction>) -> Self {
+        Program {
+            code: Code::decode(instructions),
+        }
+    }
+}
+
+pub fn parse(path: &str) -> Result<Vec<Instruction>> {
+    match path.strip_prefix('/') {
+        Some(code) => parse_instructions(code),
+        None => Err(SyntaxError::NotNoop.into()),
+    }
+}
+
+/// Parses instructions up to the first character that can't start one, without the leading `/`
+/// a whole path needs.
+pub fn parse_instructions(code: &str) -> Result<Vec<Instruction>> {
+    let mut chars = code.chars().peekable();
+    let mut instructions = Vec::new();
+
+    loop {
+        match chars.parse_instruction() {
+            ParsedInstruction::Instruction(instruction) => {
+                instructions.push(instruction);
+            }
+            ParsedInstruction::Err(e) => return Err(e.into()),
+            ParsedInstruction::CodePartStopped => break,
+        }
+    }
+
+    Ok(instructions)
+}
@@ -0,0 +1,122 @@
+//! Interactive mode running each line of path code on the same stack and memory.
+//!
+//! Lines are instructions without the leading `/` of a whole path, the stack is shown
+//! after each one:
+//!
+//! ```text
+//! > ././/..//..//
+//! [1]
+//! > .//.././././/
+//! [2]
+//! > :vars
+//! 0 Print = <function varargs arity=0 built-in>
+//! ...
+//! ```
+
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use anyhow::Result;
+use num_bigint::BigUint;
+use thiserror::Error;
+
+use crate::{
+    interpreter::Interpreter,
+    program::{self, Program},
+    stack_value::StackValue,
+    value::Value,
+};
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("Unknown command :{0}, expected :vars, :clear or :load FILE")]
+    Unknown(String),
+    #[error(":load needs a file")]
+    MissingFile,
+}
+
+/// The stack on one line, top last.
+pub(crate) fn show_stack(stack: &[StackValue]) -> String {
+    let values: Vec<String> = stack.iter().map(StackValue::describe).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// One line per variable ordered by identifier, naming the built-in functions.
+pub(crate) fn show_variables<'a>(
+    variables: impl Iterator<Item = (&'a BigUint, &'a Value)>,
+) -> String {
+    let mut variables: Vec<_> = variables.collect();
+    variables.sort_by_key(|(index, _)| *index);
+
+    let mut listing = String::new();
+    for (index, value) in variables {
+        listing.push_str(&index.to_string());
+        if let Value::Function(function) = value {
+            if function.is_built_in() {
+                listing.push_str(&format!(" {}", function.built_in()));
+            }
+        }
+        listing.push_str(&format!(
+            " = {}\n",
+            StackValue::from(value.clone()).describe()
+        ));
+    }
+    listing
+}
+
+/// Runs a meta-command, given without its `:`.
+fn command(interpreter: &mut Interpreter, command: &str) -> Result<()> {
+    let (name, argument) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    match name {
+        "vars" => print!("{}", show_variables(interpreter.memory().iter())),
+        "clear" => interpreter.clear_stack(),
+        "load" => {
+            let file = argument.trim();
+            if file.is_empty() {
+                return Err(CommandError::MissingFile.into());
+            }
+            interpreter.run(&Program::parse(fs::read_to_string(file)?.trim())?)?;
+        }
+        name => return Err(CommandError::Unknown(name.to_owned()).into()),
+    }
+    Ok(())
+}
+
+/// Runs one line, either a meta-command starting with `:` or instructions.
+fn line(interpreter: &mut Interpreter, line: &str) -> Result<()> {
+    match line.strip_prefix(':') {
+        Some(name) => command(interpreter, name),
+        None => interpreter.run(&program::parse_instructions(line)?.into()),
+    }
+}
+
+/// Reads lines from stdin until it ends, showing the stack after each one.
+///
+/// Errors are shown without stopping, whatever ran before the error stays on the stack.
+/// Lines are read one at a time so Read and ReadLn can still take input in between.
+pub fn repl() -> Result<()> {
+    let mut interpreter = Interpreter::new();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            println!();
+            return Ok(());
+        }
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        if let Err(error) = line(&mut interpreter, input) {
+            eprintln!("Error: {error}");
+        }
+        println!("{}", show_stack(interpreter.stack()));
+    }
+}
@@ -2,10 +2,10 @@ use std::cmp::Ordering;
 
 use num_bigint::{BigInt, BigUint};
 
-use crate::value::Value;
+use crate::{arithmetic, value::Value};
 
-#[derive(Clone, PartialEq)]
-pub(crate) enum StackValue {
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackValue {
     Value(Value),
     Argument(Value),
     Optional(BigUint, Value),
@@ -47,6 +47,37 @@ impl From<char> for StackValue {
     }
 }
 
+impl StackValue {
+    /// Shows the value the way it would be written, quoting strings.
+    pub fn describe(&self) -> String {
+        let value = match self {
+            StackValue::Value(Value::String(s))
+            | StackValue::Argument(Value::String(s))
+            | StackValue::Optional(_, Value::String(s)) => format!("{s:?}"),
+            StackValue::Value(v) | StackValue::Argument(v) | StackValue::Optional(_, v) => {
+                v.to_string()
+            }
+        };
+        match self {
+            StackValue::Value(_) => value,
+            StackValue::Argument(_) => format!("argument {value}"),
+            StackValue::Optional(index, _) => format!("optional {index} {value}"),
+        }
+    }
+
+    /// Whether both are marked the same way and hold equal values, see [`arithmetic::equal`].
+    pub fn equals(&self, other: &StackValue) -> bool {
+        match (self, other) {
+            (StackValue::Value(v1), StackValue::Value(v2))
+            | (StackValue::Argument(v1), StackValue::Argument(v2))
+            | (StackValue::Optional(_, v1), StackValue::Optional(_, v2)) => {
+                arithmetic::equal(v1, v2)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl PartialOrd<StackValue> for StackValue {
     fn partial_cmp(&self, other: &StackValue) -> Option<Ordering> {
         match (self, other) {
@@ -0,0 +1,110 @@
+//! Execution traces logged through the `path_lang.trace` logger, one JSON object per op run:
+//!
+//! ```text
+//! {"index":3,"depth":0,"opcode":"top + top2","operands":[],"top":"3"}
+//! ```
+//!
+//! `index` counts from the start of the function body when `depth` isn't 0, `top` is the top
+//! of the stack after the op ran written like a literal, or null when the stack is empty.
+//! Nothing shows up until a handler is added to the logger or one of its parents, the handlers
+//! of the `logging` crate put a timestamp and the logger name in front of each line, so a
+//! [`Tracer::to`] writer is better for traces meant to be diffed.
+
+use std::{
+    fmt::Write as _,
+    io::{LineWriter, Write},
+};
+
+use anyhow::Result;
+use logging::Logger;
+
+use crate::{
+    interpreter::{Hook, Interpreter},
+    op::Code,
+};
+
+/// Name of the logger traces go to.
+pub const LOGGER: &str = "path_lang.trace";
+
+/// `string` as a JSON string.
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Where trace lines go.
+enum Output {
+    Logger(Logger),
+    Writer(LineWriter<Box<dyn Write>>),
+}
+
+/// A [`Hook`] logging every op that ran at debug level, or writing it to a writer.
+pub struct Tracer {
+    output: Output,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer {
+            output: Output::Logger(logging::get(LOGGER)),
+        }
+    }
+
+    /// A tracer writing nothing but the JSON lines to `writer`, flushing after each one.
+    pub fn to(writer: impl Write + 'static) -> Tracer {
+        Tracer {
+            output: Output::Writer(LineWriter::new(Box::new(writer))),
+        }
+    }
+}
+
+impl Hook for Tracer {
+    fn after(&mut self, interpreter: &Interpreter, code: &Code, ptr: usize) -> Result<()> {
+        let index = code.position(ptr);
+        let opcode = code.ops()[ptr].opcode();
+        let operands: Vec<String> = opcode
+            .map_or_else(Vec::new, |opcode| {
+                opcode.format_operands(code.instructions(), index)
+            })
+            .iter()
+            .map(|operand| json_string(operand))
+            .collect();
+        let top = interpreter
+            .stack()
+            .last()
+            .map_or_else(|| "null".to_owned(), |value| json_string(&value.describe()));
+
+        let line = format!(
+            "{{\"index\":{index},\"depth\":{},\"opcode\":{},\"operands\":[{}],\"top\":{top}}}",
+            interpreter.memory().depth(),
+            json_string(opcode.map_or("unknown instruction", |opcode| opcode.name())),
+            operands.join(","),
+        );
+        match &mut self.output {
+            Output::Logger(logger) => logger.debug(&line),
+            Output::Writer(writer) => writeln!(writer, "{line}")?,
+        }
+        Ok(())
+    }
+}
@@ -1,25 +1,59 @@
-use std::fmt::{self, Display};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
 
 use num_bigint::{BigInt, BigUint};
+use num_traits::ToPrimitive;
 
-use crate::{function::Function, stack_value::StackValue};
+use thiserror::Error;
+
+use crate::{arithmetic::truncate, function::Function, stack_value::StackValue};
+
+#[derive(Debug, Error)]
+pub enum ConversionError {
+    #[error("Can't convert {from} to {to}")]
+    InvalidType {
+        from: &'static str,
+        to: &'static str,
+    },
+    #[error("Can't convert {from} {value} to {to}")]
+    InvalidValue {
+        from: &'static str,
+        value: String,
+        to: &'static str,
+    },
+}
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Value {
+pub enum Value {
+    Null,
     Boolean(bool),
     Integer(BigInt),
-    // Float(BigFloat),
+    Float(f64),
     String(String),
     Array(Vec<Value>),
+    Map(BTreeMap<Key, Value>),
     Function(Function),
 }
 
+/// A value that can key a map, kept in order so maps iterate the same way every run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Null,
+    Boolean(bool),
+    Integer(BigInt),
+    String(String),
+    Array(Vec<Key>),
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Value::Null => write!(f, "null"),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Integer(i) => write!(f, "{}", i),
-            // Value::Float(fl) => write!(f, "{}", fl),
+            Value::Float(fl) => write!(f, "{:?}", fl),
             Value::String(s) => write!(f, "{}", s),
             Value::Array(arr) => {
                 write!(f, "[")?;
@@ -31,33 +65,116 @@ impl Display for Value {
                 }
                 write!(f, "]")
             }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                write!(f, "}}")
+            }
             Value::Function(fu) => write!(f, "{}", fu),
         }
     }
 }
 
-impl From<Value> for BigInt {
-    fn from(value: Value) -> Self {
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Value::from(self.clone()))
+    }
+}
+
+impl Value {
+    /// The name of the variant, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Function(_) => "function",
+        }
+    }
+}
+
+impl TryFrom<Value> for BigInt {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(if b { 1 } else { 0 }.into()),
+            Value::Integer(i) => Ok(i),
+            Value::Float(fl) => truncate(fl).ok_or(ConversionError::InvalidValue {
+                from: "float",
+                value: fl.to_string(),
+                to: "integer",
+            }),
+            Value::String(s) => s.parse().map_err(|_| ConversionError::InvalidValue {
+                from: "string",
+                value: format!("{s:?}"),
+                to: "integer",
+            }),
+            value => Err(ConversionError::InvalidType {
+                from: value.type_name(),
+                to: "integer",
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Boolean(b) => if b { 1 } else { 0 }.into(),
-            Value::Integer(i) => i,
-            // Value::Float(fl) => fl.to_bigint().unwrap(),
-            Value::String(s) => s.parse().unwrap(),
-            Value::Array(_) => panic!("Can't convert array to integer"),
-            Value::Function(_) => panic!("Can't convert function to integer"),
+            Value::Null => Ok(false),
+            Value::Boolean(b) => Ok(b),
+            Value::Integer(i) => Ok(i != 0.into()),
+            Value::Float(fl) => Ok(fl != 0.0),
+            Value::String(s) => Ok(!s.is_empty()),
+            Value::Array(a) => Ok(!a.is_empty()),
+            Value::Map(m) => Ok(!m.is_empty()),
+            value => Err(ConversionError::InvalidType {
+                from: value.type_name(),
+                to: "boolean",
+            }),
         }
     }
 }
 
-impl From<Value> for bool {
-    fn from(value: Value) -> Self {
+impl TryFrom<Value> for Key {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value {
-            Value::Boolean(b) => b,
-            Value::Integer(i) => i != 0.into(),
-            // Value::Float(fl) => fl != 0.into(),
-            Value::String(s) => !s.is_empty(),
-            Value::Array(a) => !a.is_empty(),
-            Value::Function(_) => panic!("Can't convert function to boolean"),
+            Value::Null => Ok(Key::Null),
+            Value::Boolean(b) => Ok(Key::Boolean(b)),
+            Value::Integer(i) => Ok(Key::Integer(i)),
+            Value::String(s) => Ok(Key::String(s)),
+            Value::Array(a) => Ok(Key::Array(
+                a.into_iter().map(Key::try_from).collect::<Result<_, _>>()?,
+            )),
+            value => Err(ConversionError::InvalidType {
+                from: value.type_name(),
+                to: "map key",
+            }),
+        }
+    }
+}
+
+impl From<Key> for Value {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Null => Value::Null,
+            Key::Boolean(b) => Value::Boolean(b),
+            Key::Integer(i) => Value::Integer(i),
+            Key::String(s) => Value::String(s),
+            Key::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
         }
     }
 }
@@ -80,6 +197,12 @@ impl From<bool> for Value {
     }
 }
 
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
 impl From<String> for Value {
     fn from(value: String) -> Self {
         Value::String(value)
@@ -101,9 +224,12 @@ impl From<Function> for Value {
 impl PartialOrd<Value> for Value {
     fn partial_cmp(&self, other: &Value) -> Option<std::cmp::Ordering> {
         match (self, other) {
+            (Value::Null, Value::Null) => Some(std::cmp::Ordering::Equal),
             (Value::Boolean(b1), Value::Boolean(b2)) => b1.partial_cmp(b2),
             (Value::Integer(i1), Value::Integer(i2)) => i1.partial_cmp(i2),
-            // (Value::Float(f1), Value::Float(f2)) => f1.partial_cmp(f2),
+            (Value::Float(f1), Value::Float(f2)) => f1.partial_cmp(f2),
+            (Value::Integer(i), Value::Float(fl)) => i.to_f64()?.partial_cmp(fl),
+            (Value::Float(fl), Value::Integer(i)) => fl.partial_cmp(&i.to_f64()?),
             (Value::String(s1), Value::String(s2)) => s1.partial_cmp(s2),
             (Value::Array(a1), Value::Array(a2)) => a1.partial_cmp(a2),
             _ => None,